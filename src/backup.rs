@@ -0,0 +1,96 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use walkdir::WalkDir;
+
+/// Name of the copy `--backup` keeps next to each manifest it edits.
+pub const BACKUP_FILE_NAME: &str = "Cargo.toml.shear-bak";
+
+/// Where the manifest at `manifest_path`, at `relative_path` in the workspace, is backed up:
+/// next to it, or at the same relative path in `backup_dir`.
+pub fn backup_path(
+    manifest_path: &Path,
+    relative_path: &str,
+    backup_dir: Option<&Path>,
+) -> PathBuf {
+    match backup_dir {
+        Some(backup_dir) if Path::new(relative_path).is_relative() => {
+            backup_dir.join(relative_path)
+        }
+        _ => manifest_path.with_file_name(BACKUP_FILE_NAME),
+    }
+}
+
+/// Copies the manifest to `backup_path` before it is edited, unless an earlier run already
+/// backed it up, so that `cargo shear restore` always returns to the original.
+pub fn back_up(manifest_path: &Path, backup_path: &Path) -> io::Result<()> {
+    if backup_path.exists() {
+        return Ok(());
+    }
+    if let Some(dir) = backup_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::copy(manifest_path, backup_path)?;
+    Ok(())
+}
+
+/// Moves the backups of the manifests under `root` back in place, from next to them or from
+/// `backup_dir`, and returns the restored manifests.
+pub fn restore(root: &Path, backup_dir: Option<&Path>) -> io::Result<Vec<PathBuf>> {
+    let (dir, file_name) =
+        backup_dir.map_or((root, BACKUP_FILE_NAME), |backup_dir| (backup_dir, "Cargo.toml"));
+    let mut restored = vec![];
+    for entry in WalkDir::new(dir)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| entry.file_name() != "target" && entry.file_name() != ".git")
+    {
+        let entry = entry?;
+        if !entry.file_type().is_file() || entry.file_name() != file_name {
+            continue;
+        }
+        let backup_path = entry.path();
+        let manifest_path = match backup_dir {
+            Some(backup_dir) => {
+                root.join(backup_path.strip_prefix(backup_dir).map_err(io::Error::other)?)
+            }
+            None => backup_path.with_file_name("Cargo.toml"),
+        };
+        fs::copy(backup_path, &manifest_path)?;
+        fs::remove_file(backup_path)?;
+        restored.push(manifest_path);
+    }
+    Ok(restored)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{back_up, backup_path, restore};
+
+    #[test]
+    fn back_up_and_restore() {
+        let root = std::env::temp_dir().join(format!("cargo-shear-backup-{}", std::process::id()));
+        let manifest_path = root.join("a/Cargo.toml");
+        fs::create_dir_all(manifest_path.parent().unwrap()).unwrap();
+        let backup_dir = root.join("backups");
+
+        for backup_dir in [None, Some(backup_dir.as_path())] {
+            fs::write(&manifest_path, "original").unwrap();
+            let backup_path = backup_path(&manifest_path, "a/Cargo.toml", backup_dir);
+            back_up(&manifest_path, &backup_path).unwrap();
+            fs::write(&manifest_path, "first fix").unwrap();
+            back_up(&manifest_path, &backup_path).unwrap();
+            fs::write(&manifest_path, "second fix").unwrap();
+
+            assert_eq!(restore(&root, backup_dir).unwrap(), [manifest_path.clone()]);
+            assert_eq!(fs::read_to_string(&manifest_path).unwrap(), "original");
+            assert!(!backup_path.exists());
+        }
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,459 @@
+use std::{
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{
+    output::{Coverage, FixOutcome, Format, PackageReport},
+    package_analysis::DEFAULT_MAX_PARSE_SIZE,
+    CargoShear, CargoShearOptions, Severity,
+};
+
+/// Options for [`check`], a subset of the command line options.
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct CheckOptions {
+    /// Packages to check, all workspace members if empty.
+    pub package: Vec<String>,
+
+    /// Packages not to check.
+    pub exclude: Vec<String>,
+
+    /// Remove the unused dependencies from the manifests.
+    pub fix: bool,
+
+    /// Remove the unused dependencies even from manifests with uncommitted changes.
+    pub allow_dirty: bool,
+
+    /// Read the manifests directly if `cargo metadata` fails.
+    pub best_effort: bool,
+
+    /// Extra arguments for `cargo metadata`, e.g. `--offline`.
+    pub cargo_args: Vec<String>,
+}
+
+impl CheckOptions {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn package(mut self, package: impl Into<String>) -> Self {
+        self.package.push(package.into());
+        self
+    }
+
+    #[must_use]
+    pub fn exclude(mut self, package: impl Into<String>) -> Self {
+        self.exclude.push(package.into());
+        self
+    }
+
+    #[must_use]
+    pub const fn fix(mut self, fix: bool) -> Self {
+        self.fix = fix;
+        self
+    }
+
+    #[must_use]
+    pub const fn allow_dirty(mut self, allow_dirty: bool) -> Self {
+        self.allow_dirty = allow_dirty;
+        self
+    }
+
+    #[must_use]
+    pub const fn best_effort(mut self, best_effort: bool) -> Self {
+        self.best_effort = best_effort;
+        self
+    }
+
+    #[must_use]
+    pub fn cargo_arg(mut self, arg: impl Into<String>) -> Self {
+        self.cargo_args.push(arg.into());
+        self
+    }
+}
+
+/// Result of [`check`].
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    /// Packages with unused dependencies, `root` for `[workspace.dependencies]`.
+    pub packages: Vec<PackageReport>,
+
+    pub coverage: Coverage,
+
+    /// Violations of the `banned` and `allowed-only` policies.
+    pub errors: Vec<String>,
+
+    pub warnings: Vec<String>,
+}
+
+/// Exit codes of `cargo shear`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ExitStatus {
+    /// Nothing fails the run under its [`ExitPolicy`].
+    Clean = 0,
+
+    /// Unused dependencies or policy violations, or warnings with `--error-on-warnings`.
+    Failed = 1,
+
+    /// The analysis failed, so there is no indication whether anything is unused.
+    Error = 2,
+
+    /// The `cargo-shear` metadata of a manifest is invalid.
+    ConfigError = 3,
+
+    /// `--fix` failed to edit a manifest, e.g. because it changed on disk while it was analyzed.
+    FixFailed = 4,
+}
+
+impl From<ExitStatus> for ExitCode {
+    fn from(status: ExitStatus) -> Self {
+        Self::from(status as u8)
+    }
+}
+
+/// Which results fail a run, see [`Report::to_exit_code`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ExitPolicy {
+    /// Unused dependencies and policy violations fail, as in `cargo shear`.
+    #[default]
+    Default,
+
+    /// Nothing fails, as in `cargo shear --exit-zero`.
+    ExitZero,
+
+    /// Warnings fail too, as in `cargo shear --error-on-warnings`.
+    ErrorOnWarnings,
+}
+
+impl Report {
+    /// The exit code of `cargo shear` for this report, see [`Report::exit_status`].
+    #[must_use]
+    pub fn to_exit_code(&self, policy: ExitPolicy) -> ExitCode {
+        self.exit_status(policy).into()
+    }
+
+    /// [`ExitStatus::FixFailed`] if `--fix` failed for some finding, even with
+    /// [`ExitPolicy::ExitZero`], else [`ExitStatus::Failed`] if the report fails under `policy`.
+    #[must_use]
+    pub fn exit_status(&self, policy: ExitPolicy) -> ExitStatus {
+        let fix_failed = self
+            .packages
+            .iter()
+            .flat_map(|package| &package.unused_dependencies)
+            .any(|finding| matches!(finding.fix, Some(FixOutcome::Failed(_))));
+        if fix_failed {
+            return ExitStatus::FixFailed;
+        }
+        let failed = match policy {
+            ExitPolicy::Default => self.unused_dependencies() > 0 || !self.errors.is_empty(),
+            ExitPolicy::ExitZero => false,
+            ExitPolicy::ErrorOnWarnings => {
+                self.unused_dependencies() > 0
+                    || !self.errors.is_empty()
+                    || !self.warnings.is_empty()
+            }
+        };
+        if failed {
+            ExitStatus::Failed
+        } else {
+            ExitStatus::Clean
+        }
+    }
+
+    /// Number of unused dependencies which were not removed or ignored.
+    #[must_use]
+    pub fn unused_dependencies(&self) -> usize {
+        self.packages
+            .iter()
+            .flat_map(|package| &package.unused_dependencies)
+            .filter(|finding| !finding.is_resolved())
+            .count()
+    }
+}
+
+/// Checks the workspace at `path` like `cargo shear`, without printing anything,
+/// e.g. from a `cargo xtask ci` script:
+///
+/// ```ignore
+/// let report = cargo_shear::check(Path::new("."), CheckOptions::new())?;
+/// if report.unused_dependencies() > 0 {
+///     println!("{}", serde_json::to_string_pretty(&report)?);
+/// }
+/// ```
+///
+/// # Errors
+///
+/// When `cargo metadata` fails, or a manifest or source file cannot be read or parsed.
+pub fn check(path: &Path, options: CheckOptions) -> Result<Report> {
+    let mut shear = CargoShear::new(command_line_options(path.to_path_buf(), options));
+    shear.quiet = true;
+    shear.shear_with_threads()?;
+    Ok(shear.report())
+}
+
+fn command_line_options(path: PathBuf, options: CheckOptions) -> CargoShearOptions {
+    CargoShearOptions {
+        fix: options.fix,
+        package: options.package,
+        default_members: false,
+        exclude: options.exclude,
+        no_workspace: false,
+        workspace_only: false,
+        fix_only: vec![],
+        fix_except: vec![],
+        tidy_comments: false,
+        sort_deps: false,
+        format_manifest: None,
+        commit: None,
+        backup: false,
+        backup_dir: None,
+        allow_dirty: options.allow_dirty,
+        emit_patch: None,
+        skip_conventional_aux: false,
+        fail_fast: false,
+        threads: None,
+        max_parse_size: DEFAULT_MAX_PARSE_SIZE,
+        metadata_cache: false,
+        best_effort: options.best_effort,
+        min_severity: Severity::Warn,
+        format: Format::Human,
+        compare_ref: None,
+        badge: None,
+        emit_suppressions: false,
+        fix_suppress: false,
+        daemon: false,
+        deterministic: false,
+        profile_parse: false,
+        check_versions: false,
+        unused_reexports: false,
+        exit_zero: false,
+        error_on_warnings: false,
+        help_markdown: false,
+        command: None,
+        path,
+        cargo_args: options.cargo_args,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path, process::ExitCode};
+
+    use super::{check, command_line_options, CheckOptions, ExitPolicy, ExitStatus, Report};
+    use crate::{
+        output::{Coverage, Finding, FixOutcome, Location, PackageReport},
+        testing::Fixture,
+        CargoShear, CargoShearOptions,
+    };
+
+    /// A workspace of `members`, each a name and the rest of its manifest, in a new git repository.
+    fn workspace(members: &[(&str, &str)]) -> Fixture {
+        let fixture = Fixture::workspace(members).unwrap();
+        fixture.git_init().unwrap();
+        fixture
+    }
+
+    /// Runs the analysis of the workspace at `path` with the options set by `configure`.
+    fn shear(
+        path: &Path,
+        configure: impl FnOnce(&mut CargoShearOptions),
+    ) -> (anyhow::Result<()>, Report) {
+        let mut options = command_line_options(path.to_path_buf(), CheckOptions::new());
+        configure(&mut options);
+        let mut shear = CargoShear::new(options);
+        shear.quiet = true;
+        let result = shear.shear_with_threads();
+        (result, shear.report())
+    }
+
+    #[test]
+    fn check_self() {
+        let report = check(
+            Path::new(env!("CARGO_MANIFEST_DIR")),
+            CheckOptions::new().package("cargo-shear"),
+        )
+        .unwrap();
+        assert_eq!(report.unused_dependencies(), 0);
+    }
+
+    #[test]
+    fn fix_suppress_compare_ref() {
+        let fixture = workspace(&[
+            ("app", "\n[dependencies]\nhelper = { path = \"../helper\" }\n"),
+            ("helper", ""),
+        ]);
+        // The baseline is analyzed from the committed manifests, which must not be written back.
+        fixture
+            .write("app/Cargo.toml", fixture.read("app/Cargo.toml").unwrap() + "# uncommitted\n")
+            .unwrap();
+        let manifest = fixture.read("app/Cargo.toml").unwrap();
+
+        let (result, _) = shear(fixture.path(), |options| {
+            options.fix_suppress = true;
+            options.allow_dirty = true;
+            options.compare_ref = Some("HEAD".to_string());
+        });
+        assert!(result.unwrap_err().to_string().contains("`--fix-suppress`"));
+        assert_eq!(fixture.read("app/Cargo.toml").unwrap(), manifest);
+    }
+
+    #[test]
+    fn best_effort_option_conflicts() {
+        // `cargo metadata` fails on the missing path dependency, so `--best-effort` takes over.
+        let fixture = Fixture::workspace(&[(
+            "app",
+            "\n[dependencies]\nmissing = { path = \"../missing\" }\n",
+        )])
+        .unwrap();
+        let manifest = fixture.read("app/Cargo.toml").unwrap();
+
+        let (result, _) = shear(fixture.path(), |options| {
+            options.best_effort = true;
+            options.fix_suppress = true;
+            options.compare_ref = Some("HEAD".to_string());
+        });
+        assert!(result.unwrap_err().to_string().contains("`--compare-ref`"));
+        let (result, _) = shear(fixture.path(), |options| {
+            options.best_effort = true;
+            options.fix_suppress = true;
+            options.deterministic = true;
+        });
+        assert!(result.unwrap_err().to_string().contains("`--deterministic`"));
+        assert_eq!(fixture.read("app/Cargo.toml").unwrap(), manifest);
+    }
+
+    #[test]
+    fn ignored_renamed_lib() {
+        let fixture = workspace(
+            &[
+                (
+                    "app",
+                    "\n[dependencies]\nmd-5 = { path = \"../md-5\" }\n\n[package.metadata.cargo-shear]\nignored = [\"md5\"]\n",
+                ),
+                ("md-5", "\n[lib]\nname = \"md5\"\n"),
+            ],
+        );
+
+        let (result, report) = shear(fixture.path(), |_| {});
+        result.unwrap();
+        assert_eq!(report.unused_dependencies(), 0);
+        assert_eq!(CargoShear::check_config(fixture.path()).unwrap(), 0);
+    }
+
+    #[test]
+    fn fail_fast_emit_patch() {
+        let fixture = workspace(&[
+            ("app", "\n[dependencies]\nhelper = { path = \"../helper\" }\n"),
+            ("helper", ""),
+        ]);
+        let patch_path = fixture.path().join("fixes.patch");
+
+        let (result, report) = shear(fixture.path(), |options| {
+            options.fail_fast = true;
+            options.fix = true;
+            options.emit_patch = Some(patch_path.clone());
+        });
+        result.unwrap();
+        assert_eq!(report.unused_dependencies(), 1);
+        assert!(fs::read_to_string(&patch_path).unwrap().contains("-helper = "));
+    }
+
+    #[test]
+    fn fix_root_package_and_workspace_dependencies() {
+        let fixture = Fixture::workspace(&[("helper", "")]).unwrap();
+        fixture
+            .write(
+                "Cargo.toml",
+                "[package]\nname = \"app\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[dependencies]\nhelper = { path = \"helper\" }\n\n[workspace]\nmembers = [\"helper\"]\n\n[workspace.dependencies]\nitoa = \"1\"\n",
+            )
+            .unwrap();
+        fixture.write("src/lib.rs", "").unwrap();
+
+        let (result, report) = shear(fixture.path(), |options| options.fix = true);
+        result.unwrap();
+        let fixes = report
+            .packages
+            .iter()
+            .flat_map(|package| &package.unused_dependencies)
+            .map(|finding| finding.fix.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(fixes, [Some(FixOutcome::Fixed), Some(FixOutcome::Fixed)]);
+        let manifest = fixture.read("Cargo.toml").unwrap();
+        assert!(!manifest.contains("helper = ") && !manifest.contains("itoa"), "{manifest}");
+    }
+
+    #[test]
+    fn exit_codes() {
+        let report = Report {
+            packages: vec![],
+            coverage: Coverage::default(),
+            errors: vec![],
+            warnings: vec!["unknown key `ignore`".to_string()],
+        };
+        assert_eq!(report.to_exit_code(ExitPolicy::Default), ExitCode::SUCCESS);
+        assert_eq!(report.to_exit_code(ExitPolicy::ErrorOnWarnings), ExitCode::FAILURE);
+
+        let report = Report { errors: vec!["`openssl` is banned".to_string()], ..report };
+        assert_eq!(report.to_exit_code(ExitPolicy::Default), ExitCode::FAILURE);
+        assert_eq!(report.to_exit_code(ExitPolicy::ExitZero), ExitCode::SUCCESS);
+
+        let report = Report {
+            packages: vec![PackageReport {
+                name: "foo".to_string(),
+                manifest_path: "foo/Cargo.toml".to_string(),
+                unused_dependencies: vec![Finding {
+                    fix: Some(FixOutcome::Failed("manifest changed on disk".to_string())),
+                    ..Finding::new("bar".to_string())
+                }],
+                reason: None,
+                default_member: true,
+                workspace: false,
+            }],
+            ..report
+        };
+        assert_eq!(report.exit_status(ExitPolicy::ExitZero), ExitStatus::FixFailed);
+        assert_eq!(report.to_exit_code(ExitPolicy::Default), ExitCode::from(4));
+    }
+
+    #[test]
+    fn deterministic() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let first = serde_json::to_string(&check(path, CheckOptions::new()).unwrap()).unwrap();
+        let second = serde_json::to_string(&check(path, CheckOptions::new()).unwrap()).unwrap();
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn toml() {
+        let report = Report {
+            packages: vec![PackageReport {
+                name: "foo".to_string(),
+                manifest_path: "foo/Cargo.toml".to_string(),
+                unused_dependencies: vec![Finding {
+                    location: Some(Location { offset: 42, line: 8, column: 1 }),
+                    fix: Some(FixOutcome::Skipped("optional".to_string())),
+                    ..Finding::new("bar".to_string())
+                }],
+                reason: None,
+                default_member: true,
+                workspace: false,
+            }],
+            coverage: Coverage::default(),
+            errors: vec![],
+            warnings: vec![],
+        };
+        // The same schema as JSON, without the `null`s TOML cannot express.
+        let toml = toml_edit::ser::to_string_pretty(&report).unwrap();
+        let from_toml = toml_edit::de::from_str::<serde_json::Value>(&toml).unwrap();
+        let json = serde_json::to_string(&report).unwrap().replace(r#","reason":null"#, "");
+        assert_eq!(from_toml, serde_json::from_str::<serde_json::Value>(&json).unwrap());
+    }
+}
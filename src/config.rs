@@ -0,0 +1,524 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use serde_json::Value;
+use toml_edit::ImDocument;
+
+use crate::{import_collector::AttributeCrateRef, policy::BannedCrate};
+
+const KNOWN_KEYS: &[&str] = &[
+    "ignored",
+    "ignore-sources",
+    "aliases",
+    "known-imports",
+    "rules",
+    "max-dependencies",
+    "banned",
+    "allowed-only",
+    "link-only",
+];
+
+/// Values of `ignore-sources`.
+const SOURCE_KINDS: &[&str] = &["git", "path", "registry"];
+
+/// Values of the wrong type in the `cargo-shear` metadata of a manifest, which exit with
+/// [`crate::ExitStatus::ConfigError`].
+#[derive(Debug)]
+pub struct ConfigError(pub String);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// How an entry of `ignored` names a dependency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IgnoredForm {
+    PackageName,
+    DependencyKey,
+    ImportName,
+}
+
+impl fmt::Display for IgnoredForm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::PackageName => "package name",
+            Self::DependencyKey => "dependency key",
+            Self::ImportName => "import name",
+        })
+    }
+}
+
+/// Configuration from `[package.metadata.cargo-shear]` or `[workspace.metadata.cargo-shear]`.
+#[derive(Default)]
+pub struct ShearConfig<'a> {
+    /// Dependencies which are never reported as unused, by package name, dependency key or import
+    /// name, see [`ShearConfig::ignored_entry`].
+    pub ignored: HashSet<&'a str>,
+
+    /// Kinds of dependency sources which are never reported as unused: `git`, `path` or `registry`.
+    pub ignore_sources: HashSet<&'a str>,
+
+    /// Import names mapped to the dependency they refer to, e.g. `{ "json" = "serde_json" }`.
+    pub aliases: HashMap<&'a str, &'a str>,
+
+    /// Import names which are not crates, in addition to `std`, `core`, `alloc`, `proc_macro` and `test`.
+    pub known_imports: HashSet<&'a str>,
+
+    /// Packages with more direct dependencies than this are reported.
+    pub max_dependencies: Option<usize>,
+
+    /// Crates which must not be dependencies, optionally only some versions, e.g. `chrono<0.5`.
+    pub banned: Vec<BannedCrate<'a>>,
+
+    /// The only crates which may be dependencies, besides workspace members.
+    pub allowed_only: Option<HashSet<&'a str>>,
+
+    /// Package names of dependencies used only through the symbols they export to the linker, e.g.
+    /// for the `staticlib` of another member, with the reason. They are not used in the source,
+    /// which is reported as a warning instead of an unused dependency.
+    pub link_only: HashMap<&'a str, &'a str>,
+
+    /// `rules.attribute-crate-refs`, attributes which refer to crates in ways imports do not show.
+    pub attribute_crate_refs: Vec<AttributeCrateRef<'a>>,
+
+    /// Keys not recognized by cargo-shear, most likely typos.
+    pub unknown_keys: Vec<&'a str>,
+
+    /// Values with the wrong type.
+    pub errors: Vec<String>,
+}
+
+impl<'a> ShearConfig<'a> {
+    /// `metadata` is the `package.metadata` or `workspace.metadata` value from `cargo metadata`.
+    pub fn from_metadata(metadata: &'a Value) -> Self {
+        let mut config = Self::default();
+        let Some(value) = metadata.as_object().and_then(|object| object.get("cargo-shear")) else {
+            return config;
+        };
+        let Some(table) = value.as_object() else {
+            config.errors.push("`cargo-shear` should be a table".to_string());
+            return config;
+        };
+        for (key, value) in table {
+            match key.as_str() {
+                "ignored" => config.ignored = config.parse_strings(key, value),
+                "ignore-sources" => config.parse_ignore_sources(value),
+                "aliases" => config.parse_aliases(value),
+                "link-only" => config.parse_link_only(value),
+                "known-imports" => config.known_imports = config.parse_strings(key, value),
+                "rules" => config.parse_rules(value),
+                "banned" => {
+                    let mut entries =
+                        config.parse_strings(key, value).into_iter().collect::<Vec<_>>();
+                    entries.sort_unstable();
+                    for entry in entries {
+                        match BannedCrate::parse(entry) {
+                            Ok(banned) => config.banned.push(banned),
+                            Err(err) => config.errors.push(err),
+                        }
+                    }
+                }
+                "allowed-only" => config.allowed_only = Some(config.parse_strings(key, value)),
+                "max-dependencies" => match value.as_u64().and_then(|n| usize::try_from(n).ok()) {
+                    Some(max) => config.max_dependencies = Some(max),
+                    None => config.errors.push(format!(
+                        "`max-dependencies` should be a non-negative integer, found {value}"
+                    )),
+                },
+                _ => config.unknown_keys.push(key),
+            }
+        }
+        config
+    }
+
+    /// Adds the `[workspace.metadata.cargo-shear]` settings to those of a package;
+    /// package aliases, `link-only` reasons, `max-dependencies` and `allowed-only` take precedence.
+    pub fn inherit(&mut self, workspace: Self) {
+        self.ignored.extend(workspace.ignored);
+        self.ignore_sources.extend(workspace.ignore_sources);
+        for (alias, dependency) in workspace.aliases {
+            self.aliases.entry(alias).or_insert(dependency);
+        }
+        self.known_imports.extend(workspace.known_imports);
+        for (name, reason) in workspace.link_only {
+            self.link_only.entry(name).or_insert(reason);
+        }
+        self.attribute_crate_refs.extend(workspace.attribute_crate_refs);
+        self.max_dependencies = self.max_dependencies.or(workspace.max_dependencies);
+        self.banned.extend(workspace.banned);
+        self.allowed_only = self.allowed_only.take().or(workspace.allowed_only);
+    }
+
+    /// The entry of `ignored` naming the dependency declared as `key` on `package_name` and imported
+    /// as `import_name`, and how, e.g. `bitflags` or `bitflags_v2` for
+    /// `bitflags-v2 = { package = "bitflags" }`. The import name is the name of the dependency's
+    /// `[lib]` unless it is renamed, e.g. `md5` for `md-5`.
+    pub fn ignored_entry(
+        &self,
+        key: &str,
+        package_name: &str,
+        import_name: &str,
+    ) -> Option<(&'a str, IgnoredForm)> {
+        for (name, form) in [
+            (package_name, IgnoredForm::PackageName),
+            (key, IgnoredForm::DependencyKey),
+            (import_name, IgnoredForm::ImportName),
+        ] {
+            if let Some(entry) = self.ignored.get(name) {
+                return Some((entry, form));
+            }
+        }
+        None
+    }
+
+    fn parse_strings(&mut self, key: &str, value: &'a Value) -> HashSet<&'a str> {
+        let Some(items) = value.as_array() else {
+            self.errors.push(format!("`{key}` should be an array of strings"));
+            return HashSet::new();
+        };
+        let mut strings = HashSet::new();
+        for item in items {
+            if let Some(name) = item.as_str() {
+                strings.insert(name);
+            } else {
+                self.errors.push(format!("`{key}` should only contain strings, found {item}"));
+            }
+        }
+        strings
+    }
+
+    fn parse_ignore_sources(&mut self, value: &'a Value) {
+        for kind in self.parse_strings("ignore-sources", value) {
+            if SOURCE_KINDS.contains(&kind) {
+                self.ignore_sources.insert(kind);
+            } else {
+                self.errors.push(format!(
+                    "`ignore-sources` should only contain `git`, `path` or `registry`, found `{kind}`"
+                ));
+            }
+        }
+    }
+
+    fn parse_aliases(&mut self, value: &'a Value) {
+        let Some(table) = value.as_object() else {
+            self.errors.push("`aliases` should be a table of strings".to_string());
+            return;
+        };
+        for (alias, dependency) in table {
+            if let Some(dependency) = dependency.as_str() {
+                self.aliases.insert(alias, dependency);
+            } else {
+                self.errors
+                    .push(format!("`aliases.{alias}` should be a string, found {dependency}"));
+            }
+        }
+    }
+
+    fn parse_link_only(&mut self, value: &'a Value) {
+        let Some(table) = value.as_object() else {
+            self.errors.push("`link-only` should be a table of strings".to_string());
+            return;
+        };
+        for (name, reason) in table {
+            if let Some(reason) = reason.as_str() {
+                self.link_only.insert(name, reason);
+            } else {
+                self.errors.push(format!("`link-only.{name}` should be a string, found {reason}"));
+            }
+        }
+    }
+
+    fn parse_rules(&mut self, value: &'a Value) {
+        let Some(table) = value.as_object() else {
+            self.errors.push("`rules` should be a table".to_string());
+            return;
+        };
+        for (key, value) in table {
+            if key != "attribute-crate-refs" {
+                self.errors.push(format!("unknown rule `rules.{key}`"));
+                continue;
+            }
+            let Some(items) = value.as_array() else {
+                self.errors.push("`rules.attribute-crate-refs` should be an array".to_string());
+                continue;
+            };
+            for item in items {
+                let string = |field| item.get(field).and_then(Value::as_str);
+                let (Some(attr), Some(key)) = (string("attr"), string("key")) else {
+                    self.errors.push(format!(
+                        "`rules.attribute-crate-refs` entries need string `attr` and `key`, found {item}"
+                    ));
+                    continue;
+                };
+                let krate = string("crate");
+                if krate.is_none() && item.get("crate").is_some() {
+                    self.errors.push(format!(
+                        "`crate` in `rules.attribute-crate-refs` should be a string, found {item}"
+                    ));
+                    continue;
+                }
+                self.attribute_crate_refs.push(AttributeCrateRef { attr, key, krate });
+            }
+        }
+    }
+
+    /// The closest known key for an unknown key, e.g. `ignore` -> `ignored`.
+    pub fn suggest_key(unknown: &str) -> Option<&'static str> {
+        KNOWN_KEYS.iter().find(|known| edit_distance(known, unknown) <= 2).copied()
+    }
+
+    /// Describes each unknown key, pointing at `path:line:column` of the key in the manifest.
+    ///
+    /// `root` is the top level table holding the metadata, i.e. `package` or `workspace`.
+    pub fn unknown_key_messages(&self, path: &str, manifest: &str, root: &str) -> Vec<String> {
+        let document = ImDocument::parse(manifest).ok();
+        let table = document.as_ref().and_then(|document| {
+            document.get(root)?.get("metadata")?.get("cargo-shear")?.as_table_like()
+        });
+        self.unknown_keys
+            .iter()
+            .map(|key| {
+                let location = table
+                    .and_then(|table| table.get_key_value(key))
+                    .and_then(|(key, _)| key.span())
+                    .map(|span| {
+                        let (line, column) = line_column(manifest, span.start);
+                        format!(" at {path}:{line}:{column}")
+                    })
+                    .unwrap_or_default();
+                let suggestion = Self::suggest_key(key)
+                    .map(|known| format!(", did you mean `{known}`?"))
+                    .unwrap_or_default();
+                format!(
+                    "unknown key `{key}` in `[{root}.metadata.cargo-shear]`{location}{suggestion}"
+                )
+            })
+            .collect()
+    }
+}
+
+/// The `ignore-sources` kind, `git`, `path` or `registry`, from the `kind+url` source of a package
+/// id, or from [`declared_source`].
+pub fn source_kind(package_id: &str) -> &'static str {
+    // `memchr 2.7.1 (registry+https://...)` before Rust 1.77, `registry+https://...#memchr@2.7.1` after
+    let source = package_id.split_once('(').map_or(package_id, |(_, source)| source);
+    match source.split_once('+').map(|(kind, _)| kind) {
+        Some("git") => "git",
+        Some("path") => "path",
+        _ => "registry",
+    }
+}
+
+/// The `kind+` prefix of the source of a dependency declared in a manifest, for [`source_kind`].
+pub fn declared_source(dependency: &cargo_toml::Dependency) -> &'static str {
+    match dependency.detail() {
+        Some(detail) if detail.git.is_some() => "git+",
+        Some(detail) if detail.path.is_some() => "path+",
+        _ => "registry+",
+    }
+}
+
+/// Levenshtein distance between two strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// 1-based line and column of a byte offset.
+pub fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rfind('\n').map_or(offset, |i| offset - i - 1) + 1;
+    (line, column)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{declared_source, source_kind, IgnoredForm, ShearConfig};
+    use crate::import_collector::AttributeCrateRef;
+
+    #[test]
+    fn ignored_entry() {
+        let metadata =
+            json!({ "cargo-shear": { "ignored": ["bitflags", "serde_v1", "json_v1", "md5"] } });
+        let config = ShearConfig::from_metadata(&metadata);
+        assert_eq!(
+            config.ignored_entry("bitflags-v2", "bitflags", "bitflags_v2"),
+            Some(("bitflags", IgnoredForm::PackageName))
+        );
+        assert_eq!(
+            config.ignored_entry("serde_v1", "serde", "serde_v1"),
+            Some(("serde_v1", IgnoredForm::DependencyKey))
+        );
+        assert_eq!(
+            config.ignored_entry("json-v1", "serde_json", "json_v1"),
+            Some(("json_v1", IgnoredForm::ImportName))
+        );
+        assert_eq!(
+            config.ignored_entry("md-5", "md-5", "md5"),
+            Some(("md5", IgnoredForm::ImportName))
+        );
+        assert_eq!(config.ignored_entry("itoa", "itoa", "itoa"), None);
+    }
+
+    #[test]
+    fn ignored() {
+        let metadata = json!({ "cargo-shear": { "ignored": ["foo", "bar"] } });
+        let config = ShearConfig::from_metadata(&metadata);
+        assert_eq!(config.ignored.len(), 2);
+        assert!(config.unknown_keys.is_empty());
+        assert!(config.errors.is_empty());
+    }
+
+    #[test]
+    fn link_only() {
+        let metadata =
+            json!({ "cargo-shear": { "link-only": { "foo": "linked into ffi.a", "bar": 1 } } });
+        let mut config = ShearConfig::from_metadata(&metadata);
+        assert_eq!(config.link_only.get("foo"), Some(&"linked into ffi.a"));
+        assert_eq!(config.errors, ["`link-only.bar` should be a string, found 1"]);
+
+        let workspace = json!({ "cargo-shear": { "link-only": { "foo": "other", "baz": "x" } } });
+        config.inherit(ShearConfig::from_metadata(&workspace));
+        assert_eq!(config.link_only.get("foo"), Some(&"linked into ffi.a"));
+        assert_eq!(config.link_only.get("baz"), Some(&"x"));
+    }
+
+    #[test]
+    fn ignore_sources() {
+        let metadata = json!({ "cargo-shear": { "ignore-sources": ["git", "crates-io"] } });
+        let config = ShearConfig::from_metadata(&metadata);
+        assert!(config.ignore_sources.contains("git"));
+        assert_eq!(
+            config.errors,
+            vec!["`ignore-sources` should only contain `git`, `path` or `registry`, found `crates-io`"]
+        );
+    }
+
+    #[test]
+    fn max_dependencies() {
+        let metadata = json!({ "cargo-shear": { "max-dependencies": 40 } });
+        let mut config = ShearConfig::from_metadata(&metadata);
+        assert_eq!(config.max_dependencies, Some(40));
+
+        let workspace = json!({ "cargo-shear": { "max-dependencies": 20 } });
+        config.inherit(ShearConfig::from_metadata(&workspace));
+        assert_eq!(config.max_dependencies, Some(40));
+
+        let metadata = json!({ "cargo-shear": { "max-dependencies": -1 } });
+        assert_eq!(ShearConfig::from_metadata(&metadata).errors.len(), 1);
+    }
+
+    #[test]
+    fn aliases() {
+        let metadata = json!({ "cargo-shear": { "aliases": { "json": "serde_json", "bad": 1 } } });
+        let config = ShearConfig::from_metadata(&metadata);
+        assert_eq!(config.aliases.get("json"), Some(&"serde_json"));
+        assert_eq!(config.errors.len(), 1);
+    }
+
+    #[test]
+    fn known_imports() {
+        let metadata = json!({ "cargo-shear": { "known-imports": ["sysroot_crate"] } });
+        let config = ShearConfig::from_metadata(&metadata);
+        assert!(config.known_imports.contains("sysroot_crate"));
+        assert!(config.unknown_keys.is_empty());
+    }
+
+    #[test]
+    fn no_config() {
+        let metadata = json!({ "other-tool": { "ignore": true } });
+        let config = ShearConfig::from_metadata(&metadata);
+        assert!(config.ignored.is_empty());
+        assert!(config.unknown_keys.is_empty());
+        assert!(config.errors.is_empty());
+    }
+
+    #[test]
+    fn unknown_key() {
+        let metadata = json!({ "cargo-shear": { "ignore": ["foo"] } });
+        let config = ShearConfig::from_metadata(&metadata);
+        assert_eq!(config.unknown_keys, vec!["ignore"]);
+        assert_eq!(ShearConfig::suggest_key("ignore"), Some("ignored"));
+        assert_eq!(ShearConfig::suggest_key("ignord"), Some("ignored"));
+        assert_eq!(ShearConfig::suggest_key("fix"), None);
+    }
+
+    #[test]
+    fn unknown_key_location() {
+        let manifest =
+            "[package]\nname = \"a\"\n\n[package.metadata.cargo-shear]\n  ignore = [\"foo\"]\n";
+        let metadata = json!({ "cargo-shear": { "ignore": ["foo"] } });
+        let config = ShearConfig::from_metadata(&metadata);
+        assert_eq!(
+            config.unknown_key_messages("Cargo.toml", manifest, "package"),
+            vec![
+                "unknown key `ignore` in `[package.metadata.cargo-shear]` at Cargo.toml:5:3, did you mean `ignored`?"
+            ]
+        );
+    }
+
+    #[test]
+    fn wrong_types() {
+        let metadata = json!({ "cargo-shear": { "ignored": "foo" } });
+        assert_eq!(ShearConfig::from_metadata(&metadata).errors.len(), 1);
+
+        let metadata = json!({ "cargo-shear": { "ignored": ["foo", 1] } });
+        let config = ShearConfig::from_metadata(&metadata);
+        assert_eq!(config.errors.len(), 1);
+        assert!(config.ignored.contains("foo"));
+    }
+
+    #[test]
+    fn rules() {
+        let metadata = json!({ "cargo-shear": { "rules": { "attribute-crate-refs": [
+            { "attr": "sqlx", "key": "rename_all", "crate": "sqlx" },
+            { "attr": "model", "key": "backend" },
+            { "attr": "model" },
+        ] } } });
+        let config = ShearConfig::from_metadata(&metadata);
+        assert_eq!(
+            config.attribute_crate_refs,
+            vec![
+                AttributeCrateRef { attr: "sqlx", key: "rename_all", krate: Some("sqlx") },
+                AttributeCrateRef { attr: "model", key: "backend", krate: None },
+            ]
+        );
+        assert_eq!(config.errors.len(), 1);
+    }
+
+    #[test]
+    fn source_kinds() {
+        assert_eq!(
+            source_kind("memchr 2.7.1 (registry+https://github.com/rust-lang/crates.io-index)"),
+            "registry"
+        );
+        assert_eq!(source_kind("git+https://github.com/a/b#b@0.1.0"), "git");
+        assert_eq!(source_kind("path+file:///a/b#0.1.0"), "path");
+
+        let manifest = cargo_toml::Manifest::from_str(
+            "[package]\nname = \"a\"\n[dependencies]\nb = { git = \"https://github.com/a/b\" }\nc = { path = \"c\" }\nd = \"1\"\n",
+        )
+        .unwrap();
+        let kinds =
+            ["b", "c", "d"].map(|key| source_kind(declared_source(&manifest.dependencies[key])));
+        assert_eq!(kinds, ["git", "path", "registry"]);
+    }
+}
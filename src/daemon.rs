@@ -0,0 +1,222 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+
+use crate::{
+    discovery::package_rust_files,
+    file_provider::FileProvider,
+    import_collector::Imports,
+    manifest_edit::{EditOp, EditPlan, EditStyle},
+    package_analysis::{
+        analyze_manifest_with, merge_imports, process_rust_source, PackageAnalysis,
+        DEFAULT_MAX_PARSE_SIZE,
+    },
+};
+
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const SERVER_ERROR: i64 = -32000;
+
+/// Answers newline-delimited JSON-RPC 2.0 requests, keeping the imports of each source file
+/// in memory until its path is invalidated:
+///
+/// * `analyzePackage { manifestPath }` returns `{ packageName, unusedDependencies }`.
+/// * `applyFix { manifestPath }` also removes the unused dependencies from the manifest,
+///   except optional ones, and returns `{ packageName, removedDependencies }`.
+/// * `invalidatePath { path }` forgets the files at or below `path`.
+pub struct Daemon<'a> {
+    file_provider: &'a dyn FileProvider,
+
+    /// Imports of each parsed source file, by path.
+    imports: HashMap<PathBuf, Imports>,
+}
+
+impl<'a> Daemon<'a> {
+    pub fn new(file_provider: &'a dyn FileProvider) -> Self {
+        Self { file_provider, imports: HashMap::new() }
+    }
+
+    /// Serves requests from `input` until it is closed.
+    pub fn serve(&mut self, input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+        for line in input.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Some(response) = self.handle(&line) {
+                writeln!(output, "{response}")?;
+                output.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The response to a request, `None` for notifications.
+    pub fn handle(&mut self, request: &str) -> Option<Value> {
+        let request = match serde_json::from_str::<Value>(request) {
+            Ok(request) => request,
+            Err(err) => return Some(error_response(&Value::Null, PARSE_ERROR, &err.to_string())),
+        };
+        let id = request.get("id").cloned();
+        let method = request.get("method").and_then(Value::as_str).unwrap_or_default();
+        let params = request.get("params").cloned().unwrap_or(Value::Null);
+        let result = match method {
+            "analyzePackage" => self.analyze_package(&params),
+            "applyFix" => self.apply_fix(&params),
+            "invalidatePath" => self.invalidate_path(&params),
+            _ => {
+                let message = format!("unknown method `{method}`");
+                return id.map(|id| error_response(&id, METHOD_NOT_FOUND, &message));
+            }
+        };
+        let id = id?;
+        Some(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(err) => error_response(&id, SERVER_ERROR, &format!("{err:#}")),
+        })
+    }
+
+    fn analyze_package(&mut self, params: &Value) -> Result<Value> {
+        let analysis = self.analyze(&path_param(params, "manifestPath")?)?;
+        Ok(json!({
+            "packageName": analysis.package_name,
+            "unusedDependencies": analysis.unused_dependencies,
+            "warnings": analysis.warnings,
+        }))
+    }
+
+    fn apply_fix(&mut self, params: &Value) -> Result<Value> {
+        let manifest_path = path_param(params, "manifestPath")?;
+        let analysis = self.analyze(&manifest_path)?;
+
+        let original = self.file_provider.read_to_string(&manifest_path)?;
+        let mut manifest = toml_edit::DocumentMut::from_str(&original)?;
+        let mut plan = EditPlan::default();
+        let mut removed = analysis
+            .unused_dependencies
+            .iter()
+            .filter(|dependency| {
+                plan.plan(&manifest, EditOp::RemoveDependency((*dependency).clone())).is_ok()
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        plan.apply(&mut manifest, EditStyle::default());
+        removed.sort();
+
+        let serialized = manifest.to_string();
+        if serialized != original {
+            std::fs::write(&manifest_path, serialized)
+                .with_context(|| format!("failed to write {}", manifest_path.display()))?;
+        }
+        Ok(json!({ "packageName": analysis.package_name, "removedDependencies": removed }))
+    }
+
+    fn invalidate_path(&mut self, params: &Value) -> Result<Value> {
+        let path = path_param(params, "path")?;
+        // A changed manifest may change the attribute rules its package's files were parsed with.
+        let path =
+            if path.ends_with("Cargo.toml") { path.parent().unwrap_or(&path) } else { &path };
+        let before = self.imports.len();
+        self.imports.retain(|file, _| !file.starts_with(path));
+        Ok(json!({ "invalidated": before - self.imports.len() }))
+    }
+
+    fn analyze(&mut self, manifest_path: &Path) -> Result<PackageAnalysis> {
+        let dir = manifest_path.parent().context("manifest path has no parent directory")?;
+        let files = package_rust_files(dir, self.file_provider);
+        let file_provider = self.file_provider;
+        let cache = &mut self.imports;
+        analyze_manifest_with(manifest_path, file_provider, |attribute_crate_refs| {
+            for file in &files {
+                if !cache.contains_key(file) {
+                    let imports = process_rust_source(
+                        file_provider,
+                        file,
+                        DEFAULT_MAX_PARSE_SIZE,
+                        attribute_crate_refs,
+                    )?;
+                    cache.insert(file.clone(), imports);
+                }
+            }
+            Ok(merge_imports(files.iter().map(|file| &cache[file])))
+        })
+    }
+}
+
+fn path_param(params: &Value, name: &str) -> Result<PathBuf> {
+    params
+        .get(name)
+        .and_then(Value::as_str)
+        .map(PathBuf::from)
+        .with_context(|| format!("missing string parameter `{name}`"))
+}
+
+fn error_response(id: &Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::Daemon;
+    use crate::MemoryFileProvider;
+
+    #[test]
+    fn analyze_and_invalidate() {
+        let mut file_provider = MemoryFileProvider::new();
+        file_provider.insert(
+            "/virtual/Cargo.toml",
+            "[package]\nname = \"virtual\"\nversion = \"0.1.0\"\n\n[dependencies]\nitoa = \"1\"\n",
+        );
+        let mut daemon = Daemon::new(&file_provider);
+
+        let response = daemon.handle(
+            r#"{"jsonrpc":"2.0","id":1,"method":"analyzePackage","params":{"manifestPath":"/virtual/Cargo.toml"}}"#,
+        );
+        assert_eq!(
+            response,
+            Some(json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "result": {
+                    "packageName": "virtual",
+                    "unusedDependencies": ["itoa"],
+                    "warnings": [],
+                },
+            }))
+        );
+
+        let response = daemon.handle(
+            r#"{"jsonrpc":"2.0","id":2,"method":"invalidatePath","params":{"path":"/virtual"}}"#,
+        );
+        assert_eq!(
+            response,
+            Some(json!({ "jsonrpc": "2.0", "id": 2, "result": { "invalidated": 0 } }))
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let file_provider = MemoryFileProvider::new();
+        let mut daemon = Daemon::new(&file_provider);
+        assert_eq!(daemon.handle("{").unwrap()["error"]["code"], -32700);
+        assert_eq!(
+            daemon.handle(r#"{"jsonrpc":"2.0","id":1,"method":"nope"}"#).unwrap()["error"]["code"],
+            -32601
+        );
+        assert_eq!(
+            daemon
+                .handle(r#"{"jsonrpc":"2.0","id":1,"method":"analyzePackage","params":{}}"#)
+                .unwrap()["error"]["message"],
+            "missing string parameter `manifestPath`"
+        );
+        assert_eq!(daemon.handle(r#"{"jsonrpc":"2.0","method":"nope"}"#), None);
+    }
+}
@@ -0,0 +1,181 @@
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{Context, Result};
+use walkdir::WalkDir;
+
+use crate::{file_provider::FileProvider, manifest};
+
+/// Package directories of the workspace at `root`, read from the manifests without `cargo metadata`.
+///
+/// Member globs are expanded and `exclude` is honored as Cargo does.
+pub fn package_dirs(root: &Path, file_provider: &dyn FileProvider) -> Result<Vec<PathBuf>> {
+    let (manifest, _) = manifest::parse(&file_provider.read_to_string(&root.join("Cargo.toml"))?)?;
+
+    let mut dirs = vec![];
+    if manifest.package.is_some() {
+        dirs.push(root.to_path_buf());
+    }
+
+    let Some(workspace) = &manifest.workspace else { return Ok(dirs) };
+    let excluded = workspace.exclude.iter().map(|path| root.join(path)).collect::<Vec<_>>();
+    for member in &workspace.members {
+        for dir in expand_member(root, member)? {
+            if dir == root
+                || dirs.contains(&dir)
+                || excluded.iter().any(|excluded| dir.starts_with(excluded))
+            {
+                continue;
+            }
+            dirs.push(dir);
+        }
+    }
+
+    Ok(dirs)
+}
+
+/// Directories matched by a `members` entry; glob matches without a `Cargo.toml` are skipped.
+fn expand_member(root: &Path, member: &str) -> Result<Vec<PathBuf>> {
+    if !member.contains(['*', '?', '[']) {
+        return Ok(vec![root.join(member)]);
+    }
+    let pattern = root.join(member);
+    let pattern = pattern.to_str().with_context(|| format!("non UTF-8 path {member}"))?;
+    let mut dirs = glob::glob(pattern)
+        .with_context(|| format!("invalid workspace member `{member}`"))?
+        .filter_map(Result::ok)
+        .filter(|path| path.join("Cargo.toml").is_file())
+        .collect::<Vec<_>>();
+    dirs.sort();
+    Ok(dirs)
+}
+
+/// Rust files of the package in `dir`, including targets declared outside of it,
+/// e.g. `[[bin]] path = "../tools/main.rs"` or `build = "../build/common.rs"`.
+pub fn package_rust_files(dir: &Path, file_provider: &dyn FileProvider) -> Vec<PathBuf> {
+    let mut files = rust_files_in(dir);
+    for (path, is_build_script) in declared_target_paths(dir, file_provider) {
+        if path.starts_with(dir) {
+            continue;
+        }
+        if is_build_script {
+            files.push(path);
+        } else if let Some(target_dir) = path.parent() {
+            // Like targets from `cargo metadata`, modules live next to the target's root file.
+            files.extend(rust_files_in(target_dir));
+        }
+    }
+    files.sort();
+    files.dedup();
+    files
+}
+
+/// Paths of `[lib]`, `[[bin]]`, `[[example]]`, `[[test]]` and `[[bench]]` targets and of the
+/// build script, which is `true`, as declared in the manifest.
+fn declared_target_paths(dir: &Path, file_provider: &dyn FileProvider) -> Vec<(PathBuf, bool)> {
+    let Ok((manifest, _)) = file_provider
+        .read_to_string(&dir.join("Cargo.toml"))
+        .map_err(anyhow::Error::from)
+        .and_then(|source| manifest::parse(&source))
+    else {
+        return vec![];
+    };
+    let build = manifest.package.as_ref().and_then(|package| match &package.build {
+        Some(cargo_toml::OptionalFile::Path(path)) => Some((normalize(&dir.join(path)), true)),
+        _ => None,
+    });
+    manifest
+        .lib
+        .iter()
+        .chain(&manifest.bin)
+        .chain(&manifest.example)
+        .chain(&manifest.test)
+        .chain(&manifest.bench)
+        .filter_map(|product| product.path.as_ref())
+        .map(|path| (normalize(&dir.join(path)), false))
+        .chain(build)
+        .collect()
+}
+
+/// Resolves `..` lexically, so `a/../b` is not considered to be inside `a`.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir if normalized.pop() => {}
+            Component::CurDir => {}
+            _ => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Rust files below `dir`, skipping `target` and nested packages.
+pub fn rust_files_in(dir: &Path) -> Vec<PathBuf> {
+    WalkDir::new(dir)
+        .into_iter()
+        .filter_entry(|entry| {
+            entry.depth() == 0
+                || !entry.file_type().is_dir()
+                || (entry.file_name() != "target" && !entry.path().join("Cargo.toml").exists())
+        })
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "rs"))
+        .map(walkdir::DirEntry::into_path)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::{declared_target_paths, package_dirs};
+    use crate::MemoryFileProvider;
+
+    #[test]
+    fn literal_members() {
+        let mut file_provider = MemoryFileProvider::new();
+        file_provider.insert(
+            "/ws/Cargo.toml",
+            r#"
+[package]
+name = "root"
+version = "0.1.0"
+
+[workspace]
+members = [".", "crates/a", "crates/b"]
+exclude = ["crates/b"]
+"#,
+        );
+        let dirs = package_dirs(Path::new("/ws"), &file_provider).unwrap();
+        assert_eq!(dirs, vec![PathBuf::from("/ws"), PathBuf::from("/ws/crates/a")]);
+    }
+
+    #[test]
+    fn declared_targets() {
+        let mut file_provider = MemoryFileProvider::new();
+        file_provider.insert(
+            "/ws/a/Cargo.toml",
+            r#"
+[package]
+name = "a"
+version = "0.1.0"
+build = "../build/common.rs"
+
+[lib]
+path = "lib.rs"
+
+[[bin]]
+name = "tool"
+path = "../tools/main.rs"
+"#,
+        );
+        assert_eq!(
+            declared_target_paths(Path::new("/ws/a"), &file_provider),
+            vec![
+                (PathBuf::from("/ws/a/lib.rs"), false),
+                (PathBuf::from("/ws/tools/main.rs"), false),
+                (PathBuf::from("/ws/build/common.rs"), true),
+            ]
+        );
+    }
+}
@@ -0,0 +1,283 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use toml_edit::ImDocument;
+
+use crate::config::line_column;
+
+/// Features of a package and the entries they enable, as in `[features]`.
+pub struct FeatureGraph<'a> {
+    features: &'a BTreeMap<String, Vec<String>>,
+}
+
+impl<'a> FeatureGraph<'a> {
+    pub const fn new(features: &'a BTreeMap<String, Vec<String>>) -> Self {
+        Self { features }
+    }
+
+    /// Features enabled directly by `feature`, excluding dependency entries such as `dep:foo` and `foo/bar`.
+    fn enabled_features(&self, feature: &str) -> impl Iterator<Item = &'a str> + '_ {
+        self.features
+            .get(feature)
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .filter(|entry| self.features.contains_key(*entry))
+    }
+
+    /// Features enabled transitively by `feature`, not including itself unless it is part of a cycle.
+    fn reachable(&self, feature: &str) -> BTreeSet<&'a str> {
+        let mut reachable = BTreeSet::new();
+        let mut stack = self.enabled_features(feature).collect::<Vec<_>>();
+        while let Some(next) = stack.pop() {
+            if reachable.insert(next) {
+                stack.extend(self.enabled_features(next));
+            }
+        }
+        reachable
+    }
+
+    /// Groups of features which enable each other.
+    pub fn cycles(&self) -> Vec<Vec<&'a str>> {
+        let mut cycles = BTreeSet::new();
+        for feature in self.features.keys() {
+            let reachable = self.reachable(feature);
+            if !reachable.contains(feature.as_str()) {
+                continue;
+            }
+            let cycle = reachable
+                .into_iter()
+                .filter(|other| self.reachable(other).contains(feature.as_str()))
+                .collect::<Vec<_>>();
+            cycles.insert(cycle);
+        }
+        cycles.into_iter().collect()
+    }
+
+    /// `(feature, entry, enabled_by)`: `feature` lists `entry`, which its entry `enabled_by` already enables.
+    pub fn redundant_entries(&self) -> Vec<(&'a str, &'a str, &'a str)> {
+        let mut redundant = vec![];
+        for feature in self.features.keys() {
+            let entries = self.enabled_features(feature).collect::<Vec<_>>();
+            for entry in &entries {
+                let enabled_by = entries.iter().find(|other| {
+                    other != &entry
+                        && self.reachable(other).contains(entry)
+                        && !self.reachable(entry).contains(*other)
+                });
+                if let Some(enabled_by) = enabled_by {
+                    redundant.push((feature.as_str(), *entry, *enabled_by));
+                }
+            }
+        }
+        redundant
+    }
+
+    /// Features which only enable `unused_dependencies` (dependency keys, `-` replaced with `_`)
+    /// and will enable nothing once they are removed.
+    pub fn emptied_by_removal(&self, unused_dependencies: &HashSet<String>) -> Vec<&'a str> {
+        let is_unused = |name: &str| unused_dependencies.contains(&name.replace('-', "_"));
+        self.features
+            .iter()
+            .filter(|(feature, entries)| {
+                // implicit features of optional dependencies are removed along with them
+                !is_unused(feature)
+                    && !entries.is_empty()
+                    && entries.iter().all(|entry| {
+                        let dependency = entry.strip_prefix("dep:").unwrap_or_else(|| {
+                            entry.split_once('/').map_or(entry, |(dependency, _)| dependency)
+                        });
+                        let dependency = dependency.trim_end_matches('?');
+                        if self.features.contains_key(entry) {
+                            is_unused(entry)
+                        } else {
+                            is_unused(dependency)
+                        }
+                    })
+            })
+            .map(|(feature, _)| feature.as_str())
+            .collect()
+    }
+
+    /// Dependencies enabled by `feature` and the features it enables, e.g. `dep:foo` and `foo/std`.
+    /// Weak entries such as `foo?/std` do not enable `foo`.
+    fn enabled_dependencies(&self, feature: &str) -> BTreeSet<&'a str> {
+        let mut features = self.reachable(feature);
+        features.insert(feature);
+        features
+            .into_iter()
+            .filter_map(|feature| self.features.get(feature))
+            .flatten()
+            .filter_map(|entry| {
+                entry
+                    .strip_prefix("dep:")
+                    .or_else(|| entry.split_once('/').map(|(dependency, _)| dependency))
+                    .filter(|dependency| !dependency.ends_with('?'))
+            })
+            .collect()
+    }
+
+    /// `(entry, dependency)`: the `default` feature enables the unused optional `dependency` through
+    /// its `entry`, so every consumer of the package pulls it in.
+    pub fn bloated_default(&self, unused_optional: &HashSet<String>) -> Vec<(&'a str, &'a str)> {
+        let Some(default) = self.features.get("default") else { return vec![] };
+        let mut bloated = vec![];
+        for entry in default {
+            for dependency in self.enabled_dependencies(entry) {
+                if unused_optional.contains(&dependency.replace('-', "_")) {
+                    bloated.push((entry.as_str(), dependency));
+                }
+            }
+        }
+        bloated
+    }
+
+    /// Human readable advisories for all of the above.
+    pub fn messages(&self, unused_dependencies: &HashSet<String>) -> Vec<String> {
+        let mut messages = vec![];
+        for cycle in self.cycles() {
+            let features = cycle.iter().map(|f| format!("`{f}`")).collect::<Vec<_>>().join(", ");
+            messages.push(format!("features {features} enable each other cyclically"));
+        }
+        for (feature, entry, enabled_by) in self.redundant_entries() {
+            messages.push(format!(
+                "feature `{feature}` enables `{entry}`, which `{enabled_by}` already enables"
+            ));
+        }
+        for feature in self.emptied_by_removal(unused_dependencies) {
+            messages.push(format!(
+                "feature `{feature}` only enables unused dependencies and will enable nothing once they are removed"
+            ));
+        }
+        messages
+    }
+}
+
+/// 1-based line and column of `entry` in the `default` feature of `manifest`.
+pub fn default_entry_location(manifest: &str, entry: &str) -> Option<(usize, usize)> {
+    let document = ImDocument::parse(manifest).ok()?;
+    let span = document
+        .get("features")?
+        .get("default")?
+        .as_array()?
+        .iter()
+        .find(|value| value.as_str() == Some(entry))?
+        .span()?;
+    Some(line_column(manifest, span.start))
+}
+
+/// `(dependency key, line, column)` of each `"derive"` feature added by a `workspace = true`
+/// dependency on top of its `[workspace.dependencies]` entry.
+pub fn inherited_derive_features(manifest: &str) -> Vec<(String, usize, usize)> {
+    let Ok(document) = ImDocument::parse(manifest) else { return vec![] };
+    let table_names = ["dependencies", "dev-dependencies", "build-dependencies"];
+    let targets = document
+        .get("target")
+        .and_then(|item| item.as_table_like())
+        .into_iter()
+        .flat_map(|targets| targets.iter().map(|(_, target)| target));
+    let tables = table_names
+        .iter()
+        .filter_map(|name| document.get(name))
+        .chain(targets.flat_map(|target| table_names.iter().filter_map(|name| target.get(name))))
+        .filter_map(|item| item.as_table_like());
+
+    let mut locations = vec![];
+    for table in tables {
+        for (key, item) in table.iter() {
+            let Some(dependency) = item.as_table_like() else { continue };
+            if dependency.get("workspace").and_then(toml_edit::Item::as_bool) != Some(true) {
+                continue;
+            }
+            let derive = dependency
+                .get("features")
+                .and_then(|item| item.as_array())
+                .and_then(|features| features.iter().find(|value| value.as_str() == Some("derive")))
+                .and_then(toml_edit::Value::span);
+            if let Some(span) = derive {
+                let (line, column) = line_column(manifest, span.start);
+                locations.push((key.to_string(), line, column));
+            }
+        }
+    }
+    locations
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashSet};
+
+    use super::{default_entry_location, inherited_derive_features, FeatureGraph};
+
+    fn features(entries: &[(&str, &[&str])]) -> BTreeMap<String, Vec<String>> {
+        entries
+            .iter()
+            .map(|(feature, enables)| {
+                ((*feature).to_string(), enables.iter().map(ToString::to_string).collect())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn cycles() {
+        let features = features(&[("a", &["b"]), ("b", &["a"]), ("c", &["a"])]);
+        assert_eq!(FeatureGraph::new(&features).cycles(), vec![vec!["a", "b"]]);
+    }
+
+    #[test]
+    fn redundant_entries() {
+        let features =
+            features(&[("full", &["std", "alloc"]), ("std", &["alloc"]), ("alloc", &[])]);
+        assert_eq!(
+            FeatureGraph::new(&features).redundant_entries(),
+            vec![("full", "alloc", "std")]
+        );
+    }
+
+    #[test]
+    fn emptied_by_removal() {
+        let features = features(&[
+            ("json", &["dep:serde_json", "serde-json/std"]),
+            ("serde_json", &["dep:serde_json"]),
+            ("std", &["dep:itoa"]),
+        ]);
+        let unused = HashSet::from(["serde_json".to_string()]);
+        assert_eq!(FeatureGraph::new(&features).emptied_by_removal(&unused), vec!["json"]);
+    }
+
+    #[test]
+    fn bloated_default() {
+        let features = features(&[
+            ("default", &["std", "json"]),
+            ("std", &["itoa?/std"]),
+            ("json", &["serde-json/std"]),
+            ("serde-json", &["dep:serde-json"]),
+        ]);
+        let unused = HashSet::from(["itoa".to_string(), "serde_json".to_string()]);
+        assert_eq!(
+            FeatureGraph::new(&features).bloated_default(&unused),
+            vec![("json", "serde-json")]
+        );
+    }
+
+    #[test]
+    fn default_entry() {
+        let manifest = "[features]\ndefault = [\"std\", \"json\"]\n";
+        assert_eq!(default_entry_location(manifest, "json"), Some((2, 19)));
+    }
+
+    #[test]
+    fn inherited_derive() {
+        let manifest = r#"
+[dependencies]
+serde = { workspace = true, features = ["derive"] }
+clap = { version = "4", features = ["derive"] }
+
+[target.'cfg(unix)'.dev-dependencies]
+strum = { workspace = true, features = ["std", "derive"] }
+"#;
+        assert_eq!(
+            inherited_derive_features(manifest),
+            vec![("serde".to_string(), 3, 41), ("strum".to_string(), 7, 48)]
+        );
+    }
+}
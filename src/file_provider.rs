@@ -0,0 +1,65 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Reads the manifests and Rust source files analyzed by cargo-shear.
+///
+/// `cargo metadata` always reads the manifests from disk, so the workspace layout comes from disk
+/// while file contents come from the provider.
+pub trait FileProvider: Send + Sync {
+    /// # Errors
+    ///
+    /// When the file cannot be read.
+    fn read_to_string(&self, path: &Path) -> io::Result<String>;
+}
+
+/// Reads files from disk.
+pub struct RealFileProvider;
+
+impl FileProvider for RealFileProvider {
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        fs::read_to_string(path)
+    }
+}
+
+/// In-memory file contents, e.g. unsaved editor buffers, falling back to disk for other files.
+#[derive(Default)]
+pub struct MemoryFileProvider {
+    files: HashMap<PathBuf, String>,
+}
+
+impl MemoryFileProvider {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, path: impl Into<PathBuf>, content: impl Into<String>) {
+        self.files.insert(path.into(), content.into());
+    }
+}
+
+impl FileProvider for MemoryFileProvider {
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        self.files.get(path).map_or_else(|| fs::read_to_string(path), |content| Ok(content.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{FileProvider, MemoryFileProvider};
+
+    #[test]
+    fn memory_file_provider() {
+        let manifest_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml");
+        let mut provider = MemoryFileProvider::new();
+        provider.insert("/virtual/lib.rs", "use foo::bar;");
+        assert_eq!(provider.read_to_string(Path::new("/virtual/lib.rs")).unwrap(), "use foo::bar;");
+        assert!(provider.read_to_string(&manifest_path).unwrap().contains("cargo-shear"));
+        assert!(provider.read_to_string(Path::new("/virtual/missing.rs")).is_err());
+    }
+}
@@ -0,0 +1,94 @@
+use std::{
+    ffi::OsStr,
+    io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{bail, Result};
+
+use crate::file_provider::FileProvider;
+
+fn git<I, S>(dir: &Path, args: I) -> Result<String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let output = Command::new("git").current_dir(dir).args(args).output()?;
+    if !output.status.success() {
+        bail!("git failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Paths with staged changes, relative to the repository root.
+pub fn staged_files(dir: &Path) -> Result<Vec<String>> {
+    let output = git(dir, ["diff", "--cached", "--name-only"])?;
+    Ok(output.lines().map(ToString::to_string).collect())
+}
+
+/// Whether `dir` is inside a git work tree.
+pub fn is_work_tree(dir: &Path) -> bool {
+    git(dir, ["rev-parse", "--is-inside-work-tree"]).is_ok_and(|output| output.trim() == "true")
+}
+
+/// Those of `paths` with uncommitted changes or untracked, relative to the repository root.
+pub fn uncommitted_files(dir: &Path, paths: &[PathBuf]) -> Result<Vec<String>> {
+    let output = git(
+        dir,
+        [OsStr::new("status"), OsStr::new("--porcelain"), OsStr::new("--")]
+            .into_iter()
+            .chain(paths.iter().map(|p| p.as_os_str())),
+    )?;
+    // `XY path`
+    Ok(output.lines().filter_map(|line| line.get(3..)).map(ToString::to_string).collect())
+}
+
+/// Stages `paths` and commits them with `message`.
+pub fn commit(dir: &Path, paths: &[PathBuf], message: &str) -> Result<()> {
+    git(
+        dir,
+        [OsStr::new("add"), OsStr::new("--")]
+            .into_iter()
+            .chain(paths.iter().map(|p| p.as_os_str())),
+    )?;
+    git(dir, ["commit", "--quiet", "--message", message])?;
+    Ok(())
+}
+
+/// Reads files as of a git revision with `git show`, for `--compare-ref`.
+///
+/// Files which do not exist at the revision read as empty.
+pub struct GitFileProvider {
+    toplevel: PathBuf,
+    rev: String,
+}
+
+impl GitFileProvider {
+    pub fn new(dir: &Path, rev: &str) -> Result<Self> {
+        let toplevel = git(dir, ["rev-parse", "--show-toplevel"])?;
+        git(dir, ["rev-parse", "--verify", "--quiet", &format!("{rev}^{{commit}}")])?;
+        Ok(Self { toplevel: PathBuf::from(toplevel.trim()), rev: rev.to_string() })
+    }
+}
+
+impl FileProvider for GitFileProvider {
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        let relative_path = path.strip_prefix(&self.toplevel).map_err(io::Error::other)?;
+        // git paths always use `/`
+        let relative_path = relative_path
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let output = Command::new("git")
+            .current_dir(&self.toplevel)
+            .args(["show", &format!("{}:{relative_path}", self.rev)])
+            .output()?;
+        if output.status.success() {
+            String::from_utf8(output.stdout).map_err(io::Error::other)
+        } else {
+            Ok(String::new())
+        }
+    }
+}
@@ -1,38 +1,252 @@
-use std::sync::OnceLock;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::OnceLock,
+};
 
 use regex::Regex;
-use syn::{self, spanned::Spanned};
+use syn::{self, punctuated::Punctuated, spanned::Spanned};
 
 use crate::Deps;
 
-pub fn collect_imports(source_text: &str) -> syn::Result<Deps> {
+#[derive(Default)]
+pub struct Imports {
+    /// Names of the crates used by the source file.
+    pub deps: Deps,
+
+    /// Names bound by `extern crate foo as bar;`, which are visible to the whole crate.
+    pub extern_crate_renames: Deps,
+
+    /// Crates providing the macros in `#[derive(...)]`, e.g. `serde` for `#[derive(serde::Serialize)]`
+    /// or for `#[derive(Serialize)]` after `use serde::Serialize;`.
+    pub derive_crates: Deps,
+
+    /// Crates referenced in the bodies of `#[macro_export] macro_rules!` macros, by macro name.
+    pub exported_macros: HashMap<String, Deps>,
+
+    /// Paths of invoked macros, e.g. `a::client` for `a::client!()`.
+    pub macro_calls: Deps,
+
+    /// Other source files of the crate declared by the file, in order.
+    pub source_refs: Vec<SourceRef>,
+
+    /// The first two segments of paths, e.g. `serde_json::Value`, or `serde_json::*` for globs.
+    pub item_paths: Deps,
+
+    /// Names bound by `pub use` items of the file, outside of inline modules, of paths starting
+    /// with a crate, e.g. `("Value", "serde_json")` for `pub use serde_json::Value;`.
+    pub pub_uses: Vec<(String, String)>,
+
+    /// Crates of [`Imports::deps`] which are only used in items gated by `#[cfg(doc)]` or
+    /// `#[cfg(docsrs)]`, which only compile for documentation builds.
+    pub doc_only_deps: Deps,
+}
+
+/// A `mod foo;` declaration or an `include!("foo.rs")`, resolved to a file by
+/// [`crate::package_analysis`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SourceRef {
+    /// `mod name;` inside the inline modules `parents`, with the value of its `#[path]` attribute.
+    /// Inline modules with a `#[path]` attribute are listed by its value.
+    ///
+    /// `cfg_paths` are the values of `#[cfg_attr(..., path = "...")]`, one of which is used
+    /// instead depending on the target.
+    Module { parents: Vec<String>, name: String, path: Option<String>, cfg_paths: Vec<String> },
+
+    /// `include!("path")`, relative to the directory of the including file.
+    Include(String),
+}
+
+/// `attribute_crate_refs` are configured in addition to the built-in ones.
+pub fn collect_imports(
+    source_text: &str,
+    attribute_crate_refs: &[AttributeCrateRef],
+) -> syn::Result<Imports> {
     let syntax = syn::parse_str::<syn::File>(source_text)?;
-    let mut collector = ImportCollector::default();
+    let mut collector = ImportCollector { attribute_crate_refs, ..ImportCollector::default() };
     collector.visit(&syntax);
-    Ok(collector.deps)
+    let mut imports = std::mem::take(&mut collector.imports);
+    let mut doc_deps = std::mem::take(&mut collector.doc_deps);
+    imports.derive_crates.extend(
+        collector.derive_names.iter().filter_map(|name| collector.use_origins.get(name)).cloned(),
+    );
+    let is_crate = |name: &String| {
+        // `bar::` after `extern crate foo as bar;` refers to `foo`, which is already collected.
+        !imports.extern_crate_renames.contains(name)
+            // `bar::` after `mod bar;` or `use foo::bar;` refers to the local name in that module,
+            // unless written as `::bar::`.
+            && (collector.absolute_names.contains(name) || !collector.is_shadowed(name))
+    };
+    imports.deps.retain(is_crate);
+    doc_deps.retain(is_crate);
+    imports.doc_only_deps =
+        doc_deps.iter().filter(|name| !imports.deps.contains(*name)).cloned().collect();
+    imports.deps.extend(doc_deps);
+    Ok(imports)
+}
+
+/// `#[attr(key = ...)]` referring to a crate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AttributeCrateRef<'a> {
+    pub attr: &'a str,
+
+    pub key: &'a str,
+
+    /// The crate credited whenever the key is used; if `None`, the value is a string holding a
+    /// path which starts with the crate name.
+    pub krate: Option<&'a str>,
+}
+
+impl AttributeCrateRef<'static> {
+    const fn path(attr: &'static str, key: &'static str) -> Self {
+        Self { attr, key, krate: None }
+    }
+}
+
+/// Built-in [`AttributeCrateRef`]s, e.g. `#[serde(with = "humantime_serde")]` or
+/// `#[serde(crate = "rocket::serde")]`.
+const ATTRIBUTE_CRATE_REFS: &[AttributeCrateRef<'static>] = &[
+    AttributeCrateRef::path("serde", "crate"),
+    AttributeCrateRef::path("serde", "with"),
+    AttributeCrateRef::path("serde", "serialize_with"),
+    AttributeCrateRef::path("serde", "deserialize_with"),
+    AttributeCrateRef::path("serde", "default"),
+    AttributeCrateRef::path("serde", "from"),
+    AttributeCrateRef::path("serde", "try_from"),
+    AttributeCrateRef::path("serde", "into"),
+    AttributeCrateRef::path("serde_as", "crate"),
+    AttributeCrateRef::path("borsh", "crate"),
+    AttributeCrateRef::path("schemars", "crate"),
+    AttributeCrateRef::path("pyo3", "crate"),
+    AttributeCrateRef::path("component", "crate"),
+];
+
+/// Approximates [`collect_imports`] with regular expressions, without building a syntax tree.
+///
+/// Used for very large files; local modules and `use` bindings are not told apart from crates.
+pub fn scan_imports(source_text: &str) -> Imports {
+    static PATH_RE: OnceLock<Regex> = OnceLock::new();
+    static ITEM_RE: OnceLock<Regex> = OnceLock::new();
+    static MOD_RE: OnceLock<Regex> = OnceLock::new();
+    // `foo::bar`, but not `Foo::bar` or the `bar` of `foo::bar::baz`
+    let path_re = PATH_RE.get_or_init(|| {
+        Regex::new(r"(?:^|[^\w:])([a-z_]\w*)::")
+            .unwrap_or_else(|e| panic!("Failed to parse regex {e:?}"))
+    });
+    // `use foo;`, `use foo as bar;` and `extern crate foo;`
+    let item_re = ITEM_RE.get_or_init(|| {
+        Regex::new(r"\b(?:use|extern\s+crate)\s+([a-z_]\w*)\s*(?:;|as\b)")
+            .unwrap_or_else(|e| panic!("Failed to parse regex {e:?}"))
+    });
+    let deps = path_re
+        .captures_iter(source_text)
+        .chain(item_re.captures_iter(source_text))
+        .filter_map(|c| c.get(1))
+        .map(|m| m.as_str())
+        .filter(|s| !ImportCollector::is_known_import(s))
+        .map(ToString::to_string)
+        .collect();
+    let mod_re = MOD_RE.get_or_init(|| {
+        Regex::new(r"\bmod\s+([a-z_]\w*)\s*;")
+            .unwrap_or_else(|e| panic!("Failed to parse regex {e:?}"))
+    });
+    let source_refs = mod_re
+        .captures_iter(source_text)
+        .filter_map(|c| c.get(1))
+        .map(|m| SourceRef::Module {
+            parents: vec![],
+            name: m.as_str().to_string(),
+            path: None,
+            cfg_paths: vec![],
+        })
+        .collect();
+    Imports { deps, source_refs, ..Imports::default() }
 }
 
 #[derive(Default)]
-struct ImportCollector {
-    deps: Deps,
+struct ImportCollector<'r> {
+    imports: Imports,
+
+    /// User configured, in addition to `ATTRIBUTE_CRATE_REFS`.
+    attribute_crate_refs: &'r [AttributeCrateRef<'r>],
+
+    /// Names declared by `mod bar` or bound by `use foo::bar` / `use foo as bar`, with the scope
+    /// declaring them, see `scope`.
+    local_names: HashSet<(usize, String)>,
+
+    /// The scopes each name in `imports.deps` is used in.
+    use_scopes: HashMap<String, HashSet<usize>>,
+
+    /// The inline module or block being visited: 0 for the file, or its number in order.
+    scope: usize,
+
+    /// For each scope after the file, the scope whose local names it also sees: the enclosing
+    /// scope for blocks, none for modules.
+    scope_parents: Vec<Option<usize>>,
+
+    /// Names used with a leading `::`, which always refer to a crate.
+    absolute_names: Deps,
+
+    /// Names bound by `use`, mapped to the first segment of their path, e.g. `Serialize` -> `serde`.
+    use_origins: HashMap<String, String>,
+
+    /// Single segment paths in `#[derive(...)]`.
+    derive_names: Deps,
+
+    /// Directories of the enclosing inline modules.
+    inline_modules: Vec<String>,
+
+    /// Crates used in items gated by `#[cfg(doc)]`, kept apart from `imports.deps` while visiting.
+    doc_deps: Deps,
 }
 
-impl ImportCollector {
+impl ImportCollector<'_> {
     fn visit(&mut self, syntax: &syn::File) {
         use syn::visit::Visit;
         self.visit_file(syntax);
     }
 
     fn is_known_import(s: &str) -> bool {
-        matches!(s, "crate" | "super" | "self" | "std")
+        matches!(s, "crate" | "super" | "self" | "std" | "core" | "alloc" | "proc_macro" | "test")
     }
 
     fn add_import(&mut self, s: String) {
         if !Self::is_known_import(&s) {
-            self.deps.insert(s);
+            self.add_use(s);
         }
     }
 
+    fn add_use(&mut self, s: String) {
+        self.use_scopes.entry(s.clone()).or_default().insert(self.scope);
+        self.imports.deps.insert(s);
+    }
+
+    fn add_local_name(&mut self, ident: &syn::Ident) {
+        self.local_names.insert((self.scope, ident.to_string()));
+    }
+
+    /// Starts visiting a new scope, returning the current one to restore afterwards.
+    fn enter_scope(&mut self, sees_parent: bool) -> usize {
+        self.scope_parents.push(sees_parent.then_some(self.scope));
+        std::mem::replace(&mut self.scope, self.scope_parents.len())
+    }
+
+    /// Whether every scope using `name` sees a local name `name`. Modules do not see the names
+    /// declared by their parents, blocks do.
+    fn is_shadowed(&self, name: &str) -> bool {
+        let sees_local_name = |mut scope: usize| loop {
+            if self.local_names.contains(&(scope, name.to_string())) {
+                return true;
+            }
+            match scope.checked_sub(1).and_then(|index| self.scope_parents[index]) {
+                Some(parent) => scope = parent,
+                None => return false,
+            }
+        };
+        self.use_scopes
+            .get(name)
+            .is_some_and(|scopes| scopes.iter().all(|&scope| sees_local_name(scope)))
+    }
+
     fn add_ident(&mut self, ident: &syn::Ident) {
         self.add_import(ident.to_string());
     }
@@ -52,6 +266,160 @@ impl ImportCollector {
         }
     }
 
+    // `use foo::{bar, baz::Qux, *};` uses `foo::bar`, `foo::baz` and `foo::*`
+    fn collect_use_item_paths(&mut self, i: &syn::UseTree, root: Option<&syn::Ident>) {
+        use syn::UseTree;
+        let item = match i {
+            UseTree::Path(use_path) => match root {
+                Some(_) => use_path.ident.to_string(),
+                None => return self.collect_use_item_paths(&use_path.tree, Some(&use_path.ident)),
+            },
+            UseTree::Name(use_name) => use_name.ident.to_string(),
+            UseTree::Rename(use_rename) => use_rename.ident.to_string(),
+            UseTree::Glob(_) => "*".to_string(),
+            UseTree::Group(use_group) => {
+                for use_tree in &use_group.items {
+                    self.collect_use_item_paths(use_tree, root);
+                }
+                return;
+            }
+        };
+        if let Some(root) =
+            root.filter(|root| item != "self" && !Self::is_known_import(&root.to_string()))
+        {
+            self.imports.item_paths.insert(format!("{root}::{item}"));
+        }
+    }
+
+    // `pub use foo::{bar::Baz, Qux as Quux};` binds `Baz` and `Quux` from `foo`
+    fn collect_pub_uses(&mut self, i: &syn::UseTree, root: Option<&syn::Ident>) {
+        use syn::UseTree;
+        let name = match i {
+            UseTree::Path(use_path) => {
+                return self.collect_pub_uses(&use_path.tree, root.or(Some(&use_path.ident)));
+            }
+            UseTree::Name(use_name) if use_name.ident != "self" => &use_name.ident,
+            UseTree::Rename(use_rename) if use_rename.rename != "_" => &use_rename.rename,
+            UseTree::Group(use_group) => {
+                for use_tree in &use_group.items {
+                    self.collect_pub_uses(use_tree, root);
+                }
+                return;
+            }
+            UseTree::Name(_) | UseTree::Rename(_) | UseTree::Glob(_) => return,
+        };
+        let krate = root.map_or_else(|| name.to_string(), ToString::to_string);
+        if !Self::is_known_import(&krate) {
+            self.imports.pub_uses.push((name.to_string(), krate));
+        }
+    }
+
+    // `use foo::bar;`, `use foo::{self as bar};` and `use foo as bar;` bind `bar`
+    fn collect_use_bindings(&mut self, i: &syn::UseTree, nested: bool) {
+        use syn::UseTree;
+        match i {
+            UseTree::Path(use_path) => self.collect_use_bindings(&use_path.tree, true),
+            UseTree::Name(use_name) if nested && use_name.ident != "self" => {
+                self.add_local_name(&use_name.ident);
+            }
+            UseTree::Rename(use_rename) if use_rename.rename != "_" => {
+                self.add_local_name(&use_rename.rename);
+            }
+            UseTree::Group(use_group) => {
+                for use_tree in &use_group.items {
+                    self.collect_use_bindings(use_tree, nested);
+                }
+            }
+            UseTree::Name(_) | UseTree::Rename(_) | UseTree::Glob(_) => {}
+        }
+    }
+
+    // `use serde::{Serialize, de::Deserialize as De};` maps `Serialize` and `De` to `serde`
+    fn collect_use_origins(&mut self, i: &syn::UseTree, root: Option<&syn::Ident>) {
+        use syn::UseTree;
+        match i {
+            UseTree::Path(use_path) => {
+                self.collect_use_origins(&use_path.tree, root.or(Some(&use_path.ident)));
+            }
+            UseTree::Name(use_name) => {
+                if let Some(root) = root {
+                    self.use_origins.insert(use_name.ident.to_string(), root.to_string());
+                }
+            }
+            UseTree::Rename(use_rename) => {
+                if let Some(root) = root {
+                    self.use_origins.insert(use_rename.rename.to_string(), root.to_string());
+                }
+            }
+            UseTree::Group(use_group) => {
+                for use_tree in &use_group.items {
+                    self.collect_use_origins(use_tree, root);
+                }
+            }
+            UseTree::Glob(_) => {}
+        }
+    }
+
+    // `#[derive(Serialize, serde::Deserialize)]`
+    fn collect_derives(&mut self, meta_list: &syn::MetaList) {
+        let Ok(paths) =
+            meta_list.parse_args_with(Punctuated::<syn::Path, syn::Token![,]>::parse_terminated)
+        else {
+            return;
+        };
+        for path in paths {
+            let Some(first) = path.segments.first() else { continue };
+            let first = first.ident.to_string();
+            if path.segments.len() == 1 {
+                self.derive_names.insert(first);
+            } else if !Self::is_known_import(&first) {
+                self.imports.derive_crates.insert(first);
+            }
+        }
+    }
+
+    // `#[serde(with = "foo")]`, see `AttributeCrateRef`
+    fn collect_attribute_crate_refs(&mut self, meta_list: &syn::MetaList) {
+        let Some(attribute) = meta_list.path.get_ident() else { return };
+        let rules = ATTRIBUTE_CRATE_REFS
+            .iter()
+            .chain(self.attribute_crate_refs)
+            .filter(|rule| attribute == rule.attr)
+            .copied()
+            .collect::<Vec<_>>();
+        if rules.is_empty() {
+            return;
+        }
+        let Ok(metas) =
+            meta_list.parse_args_with(Punctuated::<syn::Meta, syn::Token![,]>::parse_terminated)
+        else {
+            return;
+        };
+        for meta in metas {
+            let (key, value) = match &meta {
+                syn::Meta::NameValue(name_value) => (&name_value.path, Some(&name_value.value)),
+                syn::Meta::Path(path) => (path, None),
+                syn::Meta::List(list) => (&list.path, None),
+            };
+            let Some(key) = key.get_ident() else { continue };
+            for rule in rules.iter().filter(|rule| key == rule.key) {
+                if let Some(krate) = rule.krate {
+                    self.add_import(krate.replace('-', "_"));
+                    continue;
+                }
+                let Some(syn::Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(value), .. })) = value
+                else {
+                    continue;
+                };
+                let value = value.value();
+                let first = value.trim_start_matches("::").split("::").next().unwrap_or_default();
+                if syn::parse_str::<syn::Ident>(first).is_ok() {
+                    self.add_import(first.to_string());
+                }
+            }
+        }
+    }
+
     // `foo::bar` in expressions
     fn collect_path(&mut self, path: &syn::Path) {
         if path.segments.len() <= 1 {
@@ -62,6 +430,9 @@ impl ImportCollector {
         if ident.chars().next().is_some_and(char::is_uppercase) {
             return;
         }
+        if let Some(item) = path.segments.iter().nth(1).filter(|_| !Self::is_known_import(&ident)) {
+            self.imports.item_paths.insert(format!("{ident}::{}", item.ident));
+        }
         self.add_import(ident);
     }
 
@@ -74,32 +445,189 @@ impl ImportCollector {
     // `println!("{}", foo::bar);`
     //                 ^^^^^^^^ search for the `::` pattern
     fn collect_tokens(&mut self, tokens: &proc_macro2::TokenStream) {
+        let paths = Self::token_paths(tokens);
+        for (krate, _) in &paths {
+            self.add_use(krate.clone());
+        }
+        self.imports
+            .item_paths
+            .extend(paths.into_iter().map(|(krate, item)| format!("{krate}::{item}")));
+    }
+
+    fn token_crates(tokens: &proc_macro2::TokenStream) -> Deps {
+        Self::token_paths(tokens).into_iter().map(|(krate, _)| krate).collect()
+    }
+
+    /// The first two segments of the `foo::bar` paths in `tokens`.
+    fn token_paths(tokens: &proc_macro2::TokenStream) -> Vec<(String, String)> {
         static MACRO_RE: OnceLock<Regex> = OnceLock::new();
-        let Some(source_text) = tokens.span().source_text() else { return };
-        let idents = MACRO_RE
+        let Some(source_text) = tokens.span().source_text() else { return vec![] };
+        MACRO_RE
             .get_or_init(|| {
                 Regex::new(r"(\w+)::(\w+)")
                     .unwrap_or_else(|e| panic!("Failed to parse regex {e:?}"))
             })
             .captures_iter(&source_text)
-            .filter_map(|c| c.get(1))
-            .map(|m| m.as_str())
-            .filter(|s| !Self::is_known_import(s))
-            .map(ToString::to_string)
-            .collect::<Vec<_>>();
-        self.deps.extend(idents);
+            .filter(|c| !Self::is_known_import(&c[1]))
+            .map(|c| (c[1].to_string(), c[2].to_string()))
+            .collect()
+    }
+
+    /// `mod foo;` in the tokens of macros such as `cfg_if!`.
+    fn collect_token_modules(&mut self, tokens: &proc_macro2::TokenStream) {
+        static MOD_RE: OnceLock<Regex> = OnceLock::new();
+        let Some(source_text) = tokens.span().source_text() else { return };
+        let mod_re = MOD_RE.get_or_init(|| {
+            Regex::new(r"\bmod\s+(\w+)\s*;")
+                .unwrap_or_else(|e| panic!("Failed to parse regex {e:?}"))
+        });
+        for name in mod_re.captures_iter(&source_text).filter_map(|c| c.get(1)) {
+            self.imports.source_refs.push(SourceRef::Module {
+                parents: self.inline_modules.clone(),
+                name: name.as_str().to_string(),
+                path: None,
+                cfg_paths: vec![],
+            });
+        }
+    }
+
+    /// The value of a `#[path = "..."]` attribute.
+    fn path_attribute(attrs: &[syn::Attribute]) -> Option<String> {
+        attrs.iter().find_map(|attr| Self::path_value(&attr.meta))
+    }
+
+    /// The values of `path` in `#[cfg_attr(predicate, path = "...")]` attributes, whatever the
+    /// predicates.
+    fn cfg_attr_paths(attrs: &[syn::Attribute]) -> Vec<String> {
+        attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("cfg_attr"))
+            .filter_map(|attr| {
+                attr.parse_args_with(Punctuated::<syn::Meta, syn::Token![,]>::parse_terminated).ok()
+            })
+            .flat_map(|metas| metas.into_iter().skip(1).filter_map(|meta| Self::path_value(&meta)))
+            .collect()
+    }
+
+    /// The value of `path = "..."`.
+    fn path_value(meta: &syn::Meta) -> Option<String> {
+        match meta {
+            syn::Meta::NameValue(syn::MetaNameValue {
+                path,
+                value: syn::Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(lit), .. }),
+                ..
+            }) if path.is_ident("path") => Some(lit.value()),
+            _ => None,
+        }
+    }
+
+    /// `#[cfg(doc)]`, `#[cfg(docsrs)]` or `#[cfg(all(docsrs, ...))]`.
+    fn is_doc_only(attrs: &[syn::Attribute]) -> bool {
+        attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("cfg"))
+            .filter_map(|attr| attr.parse_args::<syn::Meta>().ok())
+            .any(|meta| Self::requires_doc(&meta))
+    }
+
+    fn requires_doc(meta: &syn::Meta) -> bool {
+        match meta {
+            syn::Meta::Path(path) => path.is_ident("doc") || path.is_ident("docsrs"),
+            syn::Meta::List(list) if list.path.is_ident("all") => list
+                .parse_args_with(Punctuated::<syn::Meta, syn::Token![,]>::parse_terminated)
+                .is_ok_and(|metas| metas.iter().any(Self::requires_doc)),
+            syn::Meta::List(_) | syn::Meta::NameValue(_) => false,
+        }
+    }
+
+    fn item_attrs(i: &syn::Item) -> &[syn::Attribute] {
+        use syn::Item;
+        match i {
+            Item::Const(item) => &item.attrs,
+            Item::Enum(item) => &item.attrs,
+            Item::ExternCrate(item) => &item.attrs,
+            Item::Fn(item) => &item.attrs,
+            Item::ForeignMod(item) => &item.attrs,
+            Item::Impl(item) => &item.attrs,
+            Item::Macro(item) => &item.attrs,
+            Item::Mod(item) => &item.attrs,
+            Item::Static(item) => &item.attrs,
+            Item::Struct(item) => &item.attrs,
+            Item::Trait(item) => &item.attrs,
+            Item::TraitAlias(item) => &item.attrs,
+            Item::Type(item) => &item.attrs,
+            Item::Union(item) => &item.attrs,
+            Item::Use(item) => &item.attrs,
+            _ => &[],
+        }
+    }
+
+    // `#[macro_export] macro_rules! client { () => { ::reqwest::Client::new() } }`
+    fn collect_exported_macro(&mut self, i: &syn::ItemMacro) {
+        let Some(ident) = &i.ident else { return };
+        if i.mac.path.is_ident("macro_rules")
+            && i.attrs.iter().any(|attr| attr.path().is_ident("macro_export"))
+        {
+            self.imports
+                .exported_macros
+                .insert(ident.to_string(), Self::token_crates(&i.mac.tokens));
+        }
     }
 }
 
-impl<'a> syn::visit::Visit<'a> for ImportCollector {
+impl<'a> syn::visit::Visit<'a> for ImportCollector<'_> {
     fn visit_path(&mut self, i: &'a syn::Path) {
         self.collect_path(i);
+        if i.leading_colon.is_some() {
+            if let Some(path_segment) = i.segments.first() {
+                self.absolute_names.insert(path_segment.ident.to_string());
+            }
+        }
         syn::visit::visit_path(self, i);
     }
 
     /// A use declaration: `use std::collections::HashMap`.
     fn visit_item_use(&mut self, i: &'a syn::ItemUse) {
         self.collect_use_tree(&i.tree);
+        self.collect_use_item_paths(&i.tree, None);
+        if matches!(i.vis, syn::Visibility::Public(_)) && self.inline_modules.is_empty() {
+            self.collect_pub_uses(&i.tree, None);
+        }
+        self.collect_use_bindings(&i.tree, false);
+        self.collect_use_origins(&i.tree, None);
+        if i.leading_colon.is_some() {
+            if let syn::UseTree::Path(use_path) = &i.tree {
+                self.absolute_names.insert(use_path.ident.to_string());
+            }
+        }
+    }
+
+    /// A module declaration: `mod foo;` or `mod foo { ... }`.
+    fn visit_item_mod(&mut self, i: &'a syn::ItemMod) {
+        self.add_local_name(&i.ident);
+        let path = Self::path_attribute(&i.attrs);
+        if i.content.is_some() {
+            self.inline_modules.push(path.unwrap_or_else(|| i.ident.to_string()));
+            let parent = self.enter_scope(false);
+            syn::visit::visit_item_mod(self, i);
+            self.scope = parent;
+            self.inline_modules.pop();
+        } else {
+            self.imports.source_refs.push(SourceRef::Module {
+                parents: self.inline_modules.clone(),
+                name: i.ident.to_string(),
+                path,
+                cfg_paths: Self::cfg_attr_paths(&i.attrs),
+            });
+            syn::visit::visit_item_mod(self, i);
+        }
+    }
+
+    /// A block, e.g. a function body, whose items are only visible inside it.
+    fn visit_block(&mut self, i: &'a syn::Block) {
+        let parent = self.enter_scope(true);
+        syn::visit::visit_block(self, i);
+        self.scope = parent;
     }
 
     /// A path like `std::slice::Iter`, optionally qualified with a self-type as in <Vec<T> as `SomeTrait>::Associated`.
@@ -110,6 +638,10 @@ impl<'a> syn::visit::Visit<'a> for ImportCollector {
 
     /// A structured list within an attribute, like derive(Copy, Clone).
     fn visit_meta_list(&mut self, m: &'a syn::MetaList) {
+        if m.path.is_ident("derive") {
+            self.collect_derives(m);
+        }
+        self.collect_attribute_crate_refs(m);
         self.collect_path(&m.path);
         self.collect_tokens(&m.tokens);
     }
@@ -117,9 +649,26 @@ impl<'a> syn::visit::Visit<'a> for ImportCollector {
     /// An extern crate item: extern crate serde.
     fn visit_item_extern_crate(&mut self, i: &'a syn::ItemExternCrate) {
         self.add_ident(&i.ident);
+        if let Some((_, rename)) = i.rename.as_ref().filter(|(_, rename)| *rename != i.ident) {
+            self.imports.extern_crate_renames.insert(rename.to_string());
+        }
+    }
+
+    fn visit_item_macro(&mut self, i: &'a syn::ItemMacro) {
+        self.collect_exported_macro(i);
+        syn::visit::visit_item_macro(self, i);
     }
 
     fn visit_macro(&mut self, m: &'a syn::Macro) {
+        let segments = m.path.segments.iter().map(|segment| segment.ident.to_string());
+        self.imports.macro_calls.insert(segments.collect::<Vec<_>>().join("::"));
+        if m.path.segments.last().is_some_and(|segment| segment.ident == "include") {
+            if let Ok(lit) = m.parse_body::<syn::LitStr>() {
+                self.imports.source_refs.push(SourceRef::Include(lit.value()));
+            }
+        } else {
+            self.collect_token_modules(&m.tokens);
+        }
         self.collect_path(&m.path);
         self.collect_tokens(&m.tokens);
     }
@@ -129,7 +678,14 @@ impl<'a> syn::visit::Visit<'a> for ImportCollector {
         if let syn::Item::Verbatim(tokens) = i {
             self.collect_tokens(tokens);
         }
-        syn::visit::visit_item(self, i);
+        if Self::is_doc_only(Self::item_attrs(i)) {
+            let deps = std::mem::take(&mut self.imports.deps);
+            syn::visit::visit_item(self, i);
+            let doc_deps = std::mem::replace(&mut self.imports.deps, deps);
+            self.doc_deps.extend(doc_deps);
+        } else {
+            syn::visit::visit_item(self, i);
+        }
     }
 }
 
@@ -137,10 +693,10 @@ impl<'a> syn::visit::Visit<'a> for ImportCollector {
 mod tests {
     use std::collections::HashSet;
 
-    use super::collect_imports;
+    use super::{collect_imports, scan_imports, AttributeCrateRef};
 
     fn test(source_text: &str) {
-        let deps = collect_imports(source_text).unwrap();
+        let deps = collect_imports(source_text, &[]).unwrap().deps;
         let expected = HashSet::from_iter(["foo".to_string()]);
         assert_eq!(deps, expected, "{source_text}");
     }
@@ -175,6 +731,41 @@ mod tests {
         test("extern crate foo;");
     }
 
+    #[test]
+    fn extern_crate_rename() {
+        test("extern crate foo as bar; fn main() { let x: bar::Baz = bar::baz(); }");
+        test("extern crate foo as foo;");
+    }
+
+    #[test]
+    fn local_module() {
+        test("mod bar {} fn main() { bar::baz(); bar::info!(); foo::x(); }");
+        test("mod foo {} fn main() { ::foo::x(); }");
+    }
+
+    #[test]
+    fn use_binding() {
+        test("use foo::bar as baz; fn main() { baz::x(); }");
+        test("use foo::{bar, self as qux}; fn main() { bar::x(); qux::y(); }");
+        test("use foo::{self}; fn main() { foo::x(); }");
+    }
+
+    #[test]
+    fn shadowing_module() {
+        test("mod a { use self::b::foo; fn f() { foo::x(); } } fn main() { foo::y(); }");
+        test("mod a { mod foo {} fn f() { foo::x(); } } mod b { fn f() { foo::y(); } }");
+        test("mod foo {} mod a { fn f() { foo::x(); } }");
+        test("mod a { mod bar {} fn f() { bar::x(); } } fn main() { foo::y(); }");
+        test("fn f() { foo::g(); } fn h() { use std::fmt as foo; foo::Debug::fmt; }");
+        test("fn f() { use std::fmt as bar; { bar::Debug::fmt; } } fn main() { foo::y(); }");
+    }
+
+    #[test]
+    fn sysroot_crates() {
+        test("use core::mem; use alloc::vec::Vec; use proc_macro::TokenStream; fn main() { foo::x(); }");
+        test("extern crate test; fn main() { test::black_box(foo::x()); }");
+    }
+
     #[test]
     fn meta_list_path() {
         test(r#"#[foo::instrument(level = "debug")] fn print_with_indent() {}"#);
@@ -194,4 +785,128 @@ mod tests {
     fn macro_on_verbatim() {
         test("#[foo::ext(name = ParserExt)] pub impl Parser {}");
     }
+
+    #[test]
+    fn asm_sym_operands() {
+        test(r#"fn f() { unsafe { core::arch::asm!("call {}", sym foo::handler); } }"#);
+        test(r#"core::arch::global_asm!(".global _start", "_start: jmp {}", sym ::foo::start);"#);
+        test(
+            r#"#[naked] unsafe extern "C" fn entry() { core::arch::naked_asm!("jmp {}", sym foo::main) }"#,
+        );
+    }
+
+    #[test]
+    fn attribute_crate_refs() {
+        test(r#"#[derive(Serialize)] struct Foo { #[serde(with = "foo")] bar: Bar }"#);
+        test(r#"#[derive(Serialize)] #[serde(crate = "foo::serde")] struct Foo;"#);
+        test(r#"#[serde(rename = "bar", default = "foo::default")] struct Foo;"#);
+    }
+
+    #[test]
+    fn configured_attribute_crate_refs() {
+        let rules = [
+            AttributeCrateRef { attr: "sqlx", key: "rename_all", krate: Some("foo") },
+            AttributeCrateRef { attr: "model", key: "backend", krate: None },
+        ];
+        for source_text in [
+            r#"#[derive(FromRow)] #[sqlx(rename_all = "camelCase")] struct Foo;"#,
+            r#"#[model(backend = "foo::Postgres")] struct Foo;"#,
+        ] {
+            let deps = collect_imports(source_text, &rules).unwrap().deps;
+            assert_eq!(deps, HashSet::from_iter(["foo".to_string()]), "{source_text}");
+        }
+    }
+
+    #[test]
+    fn derive_crates() {
+        let imports = collect_imports(
+            "use serde::{Serialize, de::Deserialize as De};
+             #[derive(Debug, Serialize, De, clap::Parser, Unknown)] struct Foo;",
+            &[],
+        )
+        .unwrap();
+        let expected = HashSet::from_iter(["serde".to_string(), "clap".to_string()]);
+        assert_eq!(imports.derive_crates, expected);
+    }
+
+    #[test]
+    fn exported_macros() {
+        let imports = collect_imports(
+            "#[macro_export] macro_rules! client { () => { ::reqwest::Client::new() } }
+             macro_rules! private { () => { ::foo::x() } }
+             fn f() { a::client!(); client!(); }",
+            &[],
+        )
+        .unwrap();
+        let expected = HashSet::from_iter(["reqwest".to_string()]);
+        assert_eq!(imports.exported_macros.get("client"), Some(&expected));
+        assert!(!imports.exported_macros.contains_key("private"));
+        assert!(imports.macro_calls.contains("a::client"));
+        assert!(imports.macro_calls.contains("client"));
+    }
+
+    #[test]
+    fn reexports() {
+        let imports = collect_imports(
+            "pub use serde_json::{Value, de::Deserializer as De, self as json};
+             pub(crate) use itoa::Buffer;
+             pub use crate::local::Item;
+             mod inner { pub use ryu::Buffer; }
+             use a::{b::C, *};
+             fn f() { d::e::f(); println!(\"{}\", g::h); }",
+            &[],
+        )
+        .unwrap();
+        assert_eq!(
+            imports.pub_uses,
+            [
+                ("Value".to_string(), "serde_json".to_string()),
+                ("De".to_string(), "serde_json".to_string()),
+                ("json".to_string(), "serde_json".to_string()),
+            ]
+        );
+        let mut item_paths = imports.item_paths.into_iter().collect::<Vec<_>>();
+        item_paths.sort();
+        assert_eq!(
+            item_paths,
+            [
+                "a::*",
+                "a::b",
+                "d::e",
+                "g::h",
+                "itoa::Buffer",
+                "ryu::Buffer",
+                "serde_json::Value",
+                "serde_json::de"
+            ]
+        );
+    }
+
+    #[test]
+    fn doc_only_deps() {
+        let imports = collect_imports(
+            "#[cfg(docsrs)] pub use document_features::Item;
+             #[cfg(all(doc, feature = \"std\"))] mod docs { fn f() { serde_json::x(); } }
+             #[cfg(any(doc, test))] fn g() { foo::x(); }
+             #[cfg(doc)] fn h() { itoa::x(); }
+             fn i() { itoa::y(); }",
+            &[],
+        )
+        .unwrap();
+        let expected =
+            HashSet::from_iter(["document_features", "serde_json"].map(ToString::to_string));
+        assert_eq!(imports.doc_only_deps, expected);
+        assert!(imports.deps.is_superset(&expected));
+    }
+
+    #[test]
+    fn scan() {
+        let deps = scan_imports(
+            "use foo::bar; use baz; extern crate qux as q; use std::fmt;
+             fn f() -> Foo::Bar { a::b::c(); }",
+        )
+        .deps;
+        let expected = HashSet::from_iter(["foo", "baz", "qux", "a"].map(ToString::to_string));
+        assert_eq!(deps, expected);
+    }
 }
@@ -1,364 +1,2753 @@
+mod backup;
+mod check;
+mod config;
+mod daemon;
+mod discovery;
+mod feature_graph;
+mod file_provider;
+mod git;
 mod import_collector;
+mod manifest;
+mod manifest_edit;
+mod metadata_cache;
+mod output;
+mod package_analysis;
+mod package_spec;
+mod policy;
+pub mod testing;
 
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     env, fs,
+    hash::{DefaultHasher, Hash, Hasher},
+    io,
     path::{Path, PathBuf},
-    process::ExitCode,
+    process::{self, ExitCode},
     str::FromStr,
+    time::Instant,
 };
 
 use anyhow::{Context, Result};
-use bpaf::Bpaf;
-use cargo_metadata::{CargoOpt, Metadata, MetadataCommand, Package, TargetKind};
+use bpaf::{Args, Bpaf};
+use cargo_metadata::{
+    semver, workspace_default_members_is_missing, CargoOpt, Dependency, DependencyKind, Metadata,
+    MetadataCommand, Node, Package, PackageId, TargetKind,
+};
 use cargo_util_schemas::core::PackageIdSpec;
-use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
-use walkdir::{DirEntry, WalkDir};
+use similar::TextDiff;
 
-use crate::import_collector::collect_imports;
+pub use crate::{
+    check::{check, CheckOptions, ExitPolicy, ExitStatus, Report},
+    file_provider::{FileProvider, MemoryFileProvider, RealFileProvider},
+    output::{
+        Category, Coverage, Finding, FixOutcome, Location, PackageReport, TextEdit, TextPosition,
+        TextRange,
+    },
+    package_analysis::{PackageAnalysis, PackageAnalysisBuilder},
+};
+use crate::{
+    config::{declared_source, source_kind, ConfigError, IgnoredForm, ShearConfig},
+    feature_graph::FeatureGraph,
+    import_collector::Imports,
+    manifest_edit::{EditOp, EditPlan, EditStyle},
+    output::{
+        render_categories, render_suppressions, render_triage, Format, JsonLine, ParseProfile,
+    },
+    package_analysis::{
+        analyze_manifest, collect_reachable_imports_timed, find_unused_module_names,
+        process_rust_source, DEFAULT_MAX_PARSE_SIZE,
+    },
+    package_spec::PackageSpec,
+};
 
 const VERSION: &str = match option_env!("SHEAR_VERSION") {
     Some(v) => v,
     None => "dev",
 };
 
+const EXIT_CODES: &str = "Exit codes: 0 if nothing fails the check, 1 for unused dependencies or \
+policy violations, 2 for errors, 3 for invalid `cargo-shear` metadata, 4 if `--fix` failed to edit \
+a manifest";
+
 // options("shear") + the "batteries" feature will strip name using `bpaf::cargo_helper` from `cargo shear"
 // See <https://docs.rs/bpaf/latest/bpaf/batteries/fn.cargo_helper.html>
 #[derive(Debug, Clone, Bpaf)]
-#[bpaf(options("shear"), version(VERSION))]
+#[bpaf(options("shear"), version(VERSION), footer(EXIT_CODES))]
+#[allow(clippy::struct_excessive_bools)] // command line flags
 pub struct CargoShearOptions {
-    #[bpaf(long)]
+    /// Remove the unused dependencies from Cargo.toml
+    #[bpaf(long, env("CARGO_SHEAR_FIX"))]
     fix: bool,
 
-    /// Package(s) to check
+    /// Package(s) to check, by name, `name@version` or any other cargo package id spec
     /// If not specified, all packages are checked by default
-    #[bpaf(long, short, argument("SPEC"))]
+    #[bpaf(long, short, env("CARGO_SHEAR_PACKAGE"), argument("SPEC"))]
     package: Vec<String>,
 
+    /// Only check the members in the workspace's `default-members`
+    #[bpaf(long, env("CARGO_SHEAR_DEFAULT_MEMBERS"))]
+    default_members: bool,
+
     /// Exclude packages from the check
+    #[bpaf(long, env("CARGO_SHEAR_EXCLUDE"), argument("ARG"))]
     exclude: Vec<String>,
 
-    #[bpaf(positional("PATH"), fallback_with(default_path))]
+    /// Do not check `[workspace.dependencies]`, only the workspace members
+    #[bpaf(long, env("CARGO_SHEAR_NO_WORKSPACE"))]
+    no_workspace: bool,
+
+    /// Only check `[workspace.dependencies]` against the dependencies the members declare
+    #[bpaf(long, env("CARGO_SHEAR_WORKSPACE_ONLY"))]
+    workspace_only: bool,
+
+    /// Only apply these kinds of fixes: `unused_dependency`, `unused_workspace_dependency`,
+    /// `unused_build_dependencies` or `unused_dev_dependencies`
+    #[bpaf(long, env("CARGO_SHEAR_FIX_ONLY"), argument("CODE"))]
+    fix_only: Vec<FixKind>,
+
+    /// Do not apply these kinds of fixes
+    #[bpaf(long, env("CARGO_SHEAR_FIX_EXCEPT"), argument("CODE"))]
+    fix_except: Vec<FixKind>,
+
+    /// With `--fix`, also collapse the blank lines left behind by removed dependencies
+    #[bpaf(long, env("CARGO_SHEAR_TIDY_COMMENTS"))]
+    tidy_comments: bool,
+
+    /// With `--fix`, sort the dependency tables which were edited
+    #[bpaf(long, env("CARGO_SHEAR_SORT_DEPS"))]
+    sort_deps: bool,
+
+    /// With `--fix`, run this formatter on each modified manifest, e.g. `--format-manifest "taplo fmt"`
+    #[bpaf(long, env("CARGO_SHEAR_FORMAT_MANIFEST"), argument("COMMAND"))]
+    format_manifest: Option<String>,
+
+    /// With `--fix`, commit the modified manifests with this message
+    #[bpaf(long, env("CARGO_SHEAR_COMMIT"), argument("MESSAGE"))]
+    commit: Option<String>,
+
+    /// With `--fix`, save each manifest as `Cargo.toml.shear-bak` before editing it
+    #[bpaf(long, env("CARGO_SHEAR_BACKUP"))]
+    backup: bool,
+
+    /// With `--fix`, save each manifest into this directory before editing it, instead of next to it
+    #[bpaf(long, env("CARGO_SHEAR_BACKUP_DIR"), argument("DIR"))]
+    backup_dir: Option<PathBuf>,
+
+    /// With `--fix`, edit manifests even if they have uncommitted changes
+    #[bpaf(long, env("CARGO_SHEAR_ALLOW_DIRTY"))]
+    allow_dirty: bool,
+
+    /// Write the fixes to a patch file for `git apply` instead of editing the manifests
+    #[bpaf(long, env("CARGO_SHEAR_EMIT_PATCH"), argument("FILE"))]
+    emit_patch: Option<PathBuf>,
+
+    /// Do not check the dev-dependencies of fuzz targets and criterion benchmark packages
+    #[bpaf(long, env("CARGO_SHEAR_SKIP_CONVENTIONAL_AUX"))]
+    skip_conventional_aux: bool,
+
+    /// Stop at the first package with unused dependencies
+    #[bpaf(long, env("CARGO_SHEAR_FAIL_FAST"))]
+    fail_fast: bool,
+
+    /// Number of threads for parsing source files, defaults to the number of CPUs
+    #[bpaf(long, env("CARGO_SHEAR_THREADS"), argument("N"))]
+    threads: Option<usize>,
+
+    /// Scan files larger than this many bytes for imports instead of parsing them
+    #[bpaf(
+        long,
+        env("CARGO_SHEAR_MAX_PARSE_SIZE"),
+        argument("BYTES"),
+        fallback(DEFAULT_MAX_PARSE_SIZE),
+        display_fallback
+    )]
+    max_parse_size: usize,
+
+    /// Reuse the `cargo metadata` output under `target/` while no manifest or `Cargo.lock` changed
+    #[bpaf(long, env("CARGO_SHEAR_METADATA_CACHE"))]
+    metadata_cache: bool,
+
+    /// If `cargo metadata` fails, read the manifests directly and report approximate results
+    #[bpaf(long, env("CARGO_SHEAR_BEST_EFFORT"))]
+    best_effort: bool,
+
+    /// Hide findings below this severity: `warn` (default) or `error`
+    #[bpaf(long, env("CARGO_SHEAR_MIN_SEVERITY"), argument("SEVERITY"), fallback(Severity::Warn))]
+    min_severity: Severity,
+
+    /// Output format: `human` (default), `markdown`, `short`, `json`, `json-lines`, `toml` or `triage`
+    #[bpaf(long, env("CARGO_SHEAR_FORMAT"), argument("FORMAT"), fallback(Format::Human))]
+    format: Format,
+
+    /// Only report unused dependencies which are not already unused at this git revision
+    #[bpaf(long, env("CARGO_SHEAR_COMPARE_REF"), argument("REV"))]
+    compare_ref: Option<String>,
+
+    /// Write a shields.io endpoint payload with the number of unused dependencies, `-` for stdout
+    #[bpaf(long, env("CARGO_SHEAR_BADGE"), argument("FILE"))]
+    badge: Option<PathBuf>,
+
+    /// Print the `ignored` entries which silence the unused dependencies, instead of the report
+    #[bpaf(long, env("CARGO_SHEAR_EMIT_SUPPRESSIONS"))]
+    emit_suppressions: bool,
+
+    /// Add the unused dependencies to `ignored` in their Cargo.toml instead of removing them
+    #[bpaf(long, env("CARGO_SHEAR_FIX_SUPPRESS"))]
+    fix_suppress: bool,
+
+    /// Answer JSON-RPC requests on stdin, keeping parsed source files in memory between them
+    #[bpaf(long, env("CARGO_SHEAR_DAEMON"))]
+    daemon: bool,
+
+    /// Run the analysis twice and fail unless both runs report exactly the same results
+    #[bpaf(long, env("CARGO_SHEAR_DETERMINISTIC"))]
+    deterministic: bool,
+
+    /// List the slowest files to parse and packages to analyze, e.g. to find generated files
+    #[bpaf(long, env("CARGO_SHEAR_PROFILE_PARSE"))]
+    profile_parse: bool,
+
+    /// Warn about `*` and other version requirements without an upper bound, suggesting the locked
+    /// version
+    #[bpaf(long, env("CARGO_SHEAR_CHECK_VERSIONS"))]
+    check_versions: bool,
+
+    /// Warn about `pub use` re-exports of dependencies in `lib.rs` which no workspace member uses
+    #[bpaf(long, env("CARGO_SHEAR_UNUSED_REEXPORTS"))]
+    unused_reexports: bool,
+
+    /// Exit with 0 even if unused dependencies or policy violations are found
+    #[bpaf(long, env("CARGO_SHEAR_EXIT_ZERO"))]
+    exit_zero: bool,
+
+    /// Also exit with 1 if there are warnings
+    #[bpaf(long, env("CARGO_SHEAR_ERROR_ON_WARNINGS"))]
+    error_on_warnings: bool,
+
+    /// Print the option reference as markdown
+    #[bpaf(long, hide)]
+    help_markdown: bool,
+
+    #[bpaf(external, optional)]
+    command: Option<Command>,
+
+    #[bpaf(positional("PATH"), non_strict, fallback_with(default_path))]
     path: PathBuf,
+
+    /// Extra arguments passed through to `cargo metadata`, e.g. `cargo shear -- --offline`
+    #[bpaf(positional("CARGO_ARGS"), strict, many)]
+    cargo_args: Vec<String>,
+}
+
+#[derive(Debug, Clone, Bpaf)]
+pub enum Command {
+    /// Validate the `cargo-shear` metadata without analyzing any source files
+    #[bpaf(command("check-config"))]
+    CheckConfig {
+        #[bpaf(positional("PATH"), fallback_with(default_path))]
+        path: PathBuf,
+    },
+
+    /// Put back the manifests saved by `--fix --backup` or `--fix --backup-dir`
+    #[bpaf(command("restore"))]
+    Restore {
+        /// Restore from the directory given to `--backup-dir`
+        #[bpaf(long("from"), argument("DIR"))]
+        backup_dir: Option<PathBuf>,
+
+        #[bpaf(positional("PATH"), fallback_with(default_path))]
+        path: PathBuf,
+    },
+
+    /// Print a completion script for `bash`, `zsh`, `fish` or `elvish`
+    #[bpaf(command("completions"))]
+    Completions {
+        #[bpaf(positional("SHELL"))]
+        shell: Shell,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Elvish,
+}
+
+impl FromStr for Shell {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bash" => Ok(Self::Bash),
+            "zsh" => Ok(Self::Zsh),
+            "fish" => Ok(Self::Fish),
+            "elvish" => Ok(Self::Elvish),
+            _ => Err(format!("expected `bash`, `zsh`, `fish` or `elvish`, found `{s}`")),
+        }
+    }
+}
+
+/// Unused dependencies are errors, configuration problems found during analysis are warnings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warn,
+    Error,
+}
+
+impl FromStr for Severity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "warn" => Ok(Self::Warn),
+            "error" => Ok(Self::Error),
+            _ => Err(format!("expected `warn` or `error`, found `{s}`")),
+        }
+    }
+}
+
+/// What a fix removes, selected with `--fix-only` and `--fix-except`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FixKind {
+    /// A dependency of a package.
+    UnusedDependency,
+    /// An entry in `[workspace.dependencies]`.
+    UnusedWorkspaceDependency,
+    /// `[build-dependencies]` of a package without a build script.
+    UnusedBuildDependencies,
+    /// `[dev-dependencies]` of a package without tests, benchmarks, examples or doctests.
+    UnusedDevDependencies,
+}
+
+impl FixKind {
+    const fn code(self) -> &'static str {
+        match self {
+            Self::UnusedDependency => "unused_dependency",
+            Self::UnusedWorkspaceDependency => "unused_workspace_dependency",
+            Self::UnusedBuildDependencies => "unused_build_dependencies",
+            Self::UnusedDevDependencies => "unused_dev_dependencies",
+        }
+    }
+
+    /// The dependency table removed as a whole by this kind of fix.
+    const fn table_name(self) -> Option<&'static str> {
+        match self {
+            Self::UnusedBuildDependencies => Some("build-dependencies"),
+            Self::UnusedDevDependencies => Some("dev-dependencies"),
+            Self::UnusedDependency | Self::UnusedWorkspaceDependency => None,
+        }
+    }
+
+    /// The kind of the dependencies in [`Self::table_name`].
+    const fn dependency_kind(self) -> Option<DependencyKind> {
+        match self {
+            Self::UnusedBuildDependencies => Some(DependencyKind::Build),
+            Self::UnusedDevDependencies => Some(DependencyKind::Development),
+            Self::UnusedDependency | Self::UnusedWorkspaceDependency => None,
+        }
+    }
+}
+
+impl FromStr for FixKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "unused_dependency" => Ok(Self::UnusedDependency),
+            "unused_workspace_dependency" => Ok(Self::UnusedWorkspaceDependency),
+            "unused_build_dependencies" => Ok(Self::UnusedBuildDependencies),
+            "unused_dev_dependencies" => Ok(Self::UnusedDevDependencies),
+            _ => Err(format!(
+                "expected `unused_dependency`, `unused_workspace_dependency`, \
+                 `unused_build_dependencies` or `unused_dev_dependencies`, found `{s}`"
+            )),
+        }
+    }
 }
 
 fn default_path() -> Result<PathBuf> {
     env::current_dir().map_err(|err| anyhow::anyhow!(err))
 }
 
+/// The parts of a workspace member's imports relevant to the members depending on it.
+struct MemberUsage {
+    /// Crates referenced by its exported macros, by macro name.
+    exported_macros: HashMap<String, Deps>,
+
+    /// See [`Imports::item_paths`].
+    item_paths: Deps,
+}
+
+impl MemberUsage {
+    fn new(imports: &Imports) -> Self {
+        Self {
+            exported_macros: imports.exported_macros.clone(),
+            item_paths: imports.item_paths.clone(),
+        }
+    }
+}
+
 pub struct CargoShear {
     options: CargoShearOptions,
 
+    file_provider: Box<dyn FileProvider>,
+
     unused_dependencies: usize,
 
     fixed_dependencies: usize,
+
+    suppressed_warnings: usize,
+
+    /// Unified diffs of the fixes for `--emit-patch`.
+    patch: String,
+
+    /// Manifests modified by `--fix`.
+    fixed_manifests: Vec<PathBuf>,
+
+    /// Manifest contents when `cargo metadata` read them, so `--fix` does not overwrite concurrent edits.
+    manifest_hashes: HashMap<PathBuf, u64>,
+
+    reports: Vec<PackageReport>,
+
+    coverage: Coverage,
+
+    parse_profile: ParseProfile,
+
+    /// What other members can use of each analyzed workspace member.
+    member_usage: HashMap<PackageId, MemberUsage>,
+
+    /// Workspace members which are not in `default-members`, whose findings are marked.
+    non_default_members: HashSet<PackageId>,
+
+    /// `(package name, dependency)` pairs not reported because of `--compare-ref`, with no package
+    /// name for `[workspace.dependencies]`.
+    pre_existing: HashSet<(Option<String>, String)>,
+
+    /// Violations of the `banned` and `allowed-only` policies.
+    errors: Vec<String>,
+
+    /// Warnings which were not suppressed by `--min-severity`.
+    warnings: Vec<String>,
+
+    /// Print nothing, for [`check`].
+    quiet: bool,
 }
 
 type Deps = HashSet<String>;
 
+fn content_hash(content: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    hasher.finish()
+}
+
 impl CargoShear {
     #[must_use]
-    pub const fn new(options: CargoShearOptions) -> Self {
-        Self { options, unused_dependencies: 0, fixed_dependencies: 0 }
+    pub fn new(options: CargoShearOptions) -> Self {
+        Self {
+            quiet: options.emit_suppressions,
+            options,
+            file_provider: Box::new(RealFileProvider),
+            unused_dependencies: 0,
+            fixed_dependencies: 0,
+            suppressed_warnings: 0,
+            patch: String::new(),
+            fixed_manifests: vec![],
+            manifest_hashes: HashMap::new(),
+            reports: vec![],
+            coverage: Coverage::default(),
+            parse_profile: ParseProfile::default(),
+            member_usage: HashMap::new(),
+            non_default_members: HashSet::new(),
+            pre_existing: HashSet::new(),
+            errors: vec![],
+            warnings: vec![],
+        }
     }
 
+    /// Read manifests and Rust source files through `file_provider` instead of from disk.
     #[must_use]
-    pub fn run(mut self) -> ExitCode {
-        println!("Analyzing {}", self.options.path.to_string_lossy());
-        println!();
-
-        match self.shear() {
-            Ok(()) => {
-                let has_fixed = self.fixed_dependencies > 0;
+    pub fn with_file_provider(mut self, file_provider: impl FileProvider + 'static) -> Self {
+        self.file_provider = Box::new(file_provider);
+        self
+    }
 
-                if has_fixed {
-                    println!("Fixed {} dependencies!", self.fixed_dependencies);
-                }
+    #[must_use]
+    pub fn run(mut self) -> ExitCode {
+        if self.options.help_markdown {
+            print!("{}", cargo_shear_options().render_markdown("cargo shear"));
+            return ExitCode::SUCCESS;
+        }
 
-                let has_deps = (self.unused_dependencies - self.fixed_dependencies) > 0;
-
-                if has_deps {
-                    println!(
-                        "\n\
-                        If you believe cargo-shear has detected an unused dependency incorrectly,\n\
-                        you can add the dependency to the list of dependencies to ignore in the\n\
-                        `[package.metadata.cargo-shear]` section of the appropriate Cargo.toml.\n\
-                        \n\
-                        For example:\n\
-                        \n\
-                        [package.metadata.cargo-shear]\n\
-                        ignored = [\"crate-name\"]"
-                    );
-                } else {
-                    println!("No unused dependencies!");
+        if self.options.daemon {
+            let mut daemon = daemon::Daemon::new(self.file_provider.as_ref());
+            return match daemon.serve(io::stdin().lock(), io::stdout().lock()) {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(err) => {
+                    eprintln!("{err}");
+                    ExitStatus::Error.into()
                 }
+            };
+        }
 
-                // returns 0 if no deps, 1 if has deps
-                ExitCode::from(u8::from(has_deps))
+        match &self.options.command {
+            Some(Command::CheckConfig { path }) => return Self::run_check_config(path),
+            Some(Command::Completions { shell }) => return Self::print_completions(*shell),
+            Some(Command::Restore { backup_dir, path }) => {
+                return Self::run_restore(path, backup_dir.as_deref());
             }
+            None => {}
+        }
+
+        if self.options.format == Format::Human && !self.quiet {
+            println!("Analyzing {}", self.options.path.to_string_lossy());
+            println!();
+        }
+
+        let report = match self.shear_with_threads().and_then(|()| self.verify_deterministic()) {
+            Ok(()) => self.report(),
             Err(err) => {
                 println!("{err}");
-                ExitCode::from(2)
+                return if err.is::<ConfigError>() {
+                    ExitStatus::ConfigError.into()
+                } else {
+                    ExitStatus::Error.into()
+                };
             }
+        };
+        if let Err(err) = self.print_report(&report).and_then(|()| self.write_badge(&report)) {
+            println!("{err}");
+            return ExitStatus::Error.into();
         }
+        report.to_exit_code(self.exit_policy())
     }
 
-    fn shear(&mut self) -> Result<()> {
-        let metadata = MetadataCommand::new()
-            .features(CargoOpt::AllFeatures)
-            .current_dir(&self.options.path)
-            .exec()?;
-
-        let mut package_dependencies = HashSet::new();
-        for package in metadata.workspace_packages() {
-            // Skip if package is in the exclude list
-            if self.options.exclude.iter().any(|name| name == &package.name) {
-                continue;
-            }
-
-            // Skip if specific packages are specified and this package is not in the list
-            if !self.options.package.is_empty()
-                && !self.options.package.iter().any(|name| name == &package.name)
-            {
-                continue;
-            }
-
-            let deps = self.shear_package(&metadata, package)?;
-            package_dependencies.extend(deps);
+    /// Takes the results of [`Self::shear`].
+    fn report(&mut self) -> Report {
+        Report {
+            packages: std::mem::take(&mut self.reports),
+            coverage: std::mem::take(&mut self.coverage),
+            errors: std::mem::take(&mut self.errors),
+            warnings: std::mem::take(&mut self.warnings),
         }
-
-        self.shear_workspace(&metadata, &package_dependencies)
     }
 
-    fn shear_workspace(
-        &mut self,
-        workspace_metadata: &Metadata,
-        all_pkg_deps: &Deps,
-    ) -> Result<()> {
-        if workspace_metadata.workspace_packages().len() <= 1 {
+    fn print_report(&self, report: &Report) -> Result<()> {
+        if self.options.emit_suppressions {
+            print!("{}", render_suppressions(&report.packages).unwrap_or_default());
             return Ok(());
         }
-        let metadata_path = workspace_metadata.workspace_root.as_std_path();
-        let cargo_toml_path = metadata_path.join("Cargo.toml");
-        let metadata = cargo_toml::Manifest::from_path(&cargo_toml_path)?;
-        let Some(workspace) = &metadata.workspace else { return Ok(()) };
+        match self.options.format {
+            Format::Human => self.print_summary(report),
+            Format::Markdown => print!(
+                "{}",
+                output::render_markdown(
+                    &report.packages,
+                    &report.coverage,
+                    &report.errors,
+                    &report.warnings
+                )
+            ),
+            Format::Short => {
+                print!(
+                    "{}",
+                    output::render_short(&report.packages, &report.errors, &report.warnings)
+                );
+            }
+            Format::Json => println!("{}", serde_json::to_string_pretty(report)?),
+            // Findings, errors and warnings are printed as they are found.
+            Format::JsonLines => println!(
+                "{}",
+                JsonLine::Summary {
+                    unused_dependencies: report.unused_dependencies(),
+                    coverage: &report.coverage,
+                }
+            ),
+            Format::Toml => print!("{}", toml_edit::ser::to_string_pretty(report)?),
+            Format::Triage => print!("{}", render_triage(&report.packages)),
+        }
+        Ok(())
+    }
 
-        let ignored_package_names =
-            Self::get_ignored_package_names(&workspace_metadata.workspace_metadata);
+    const fn exit_policy(&self) -> ExitPolicy {
+        if self.options.exit_zero {
+            ExitPolicy::ExitZero
+        } else if self.options.error_on_warnings {
+            ExitPolicy::ErrorOnWarnings
+        } else {
+            ExitPolicy::Default
+        }
+    }
 
-        let workspace_deps = workspace
-            .dependencies
-            .iter()
-            .map(|(key, dependency)| {
-                // renamed package, e.g. `ustr = { package = "ustr-fxhash", version = "1.0.0" }`
-                dependency
-                    .detail()
-                    .and_then(|detail| detail.package.as_ref())
-                    .unwrap_or(key)
-                    .clone()
-            })
-            .filter(|name| !ignored_package_names.contains(name.as_str()))
-            .collect::<HashSet<String>>();
+    /// Runs [`Self::shear`] in a dedicated thread pool if `--threads` is given.
+    fn shear_with_threads(&mut self) -> Result<()> {
+        let Some(threads) = self.options.threads else { return self.shear() };
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build()?;
+        pool.install(|| self.shear())
+    }
 
-        let unused_deps = workspace_deps.difference(all_pkg_deps).cloned().collect::<Vec<_>>();
+    /// The options for another analysis of the workspace, which must not write anything.
+    fn read_only_options(&self) -> CargoShearOptions {
+        let mut options = self.options.clone();
+        options.fix = false;
+        options.fix_suppress = false;
+        options.backup = false;
+        options.backup_dir = None;
+        options.emit_patch = None;
+        options.commit = None;
+        options.format_manifest = None;
+        options
+    }
 
-        if unused_deps.is_empty() {
+    /// For `--deterministic`, analyzes the workspace again without output and compares the results.
+    fn verify_deterministic(&mut self) -> Result<()> {
+        if !self.options.deterministic {
             return Ok(());
         }
+        let mut second = Self::new(self.read_only_options());
+        second.quiet = true;
+        second.file_provider =
+            std::mem::replace(&mut self.file_provider, Box::new(RealFileProvider));
+        let result = second.shear_with_threads();
+        self.file_provider = second.file_provider;
+        result?;
 
-        let path = cargo_toml_path
-            .strip_prefix(env::current_dir()?)
-            .unwrap_or(&cargo_toml_path)
-            .to_string_lossy();
-        println!("root -- {path}:",);
-        for unused_dep in &unused_deps {
-            println!("  {unused_dep}");
+        let first = serde_json::to_string_pretty(&(&self.reports, &self.errors, &self.warnings))?;
+        let second =
+            serde_json::to_string_pretty(&(&second.reports, &second.errors, &second.warnings))?;
+        if first != second {
+            let diff = TextDiff::from_lines(&first, &second)
+                .unified_diff()
+                .header("first run", "second run")
+                .to_string();
+            anyhow::bail!("The results of two runs differ:\n{diff}");
         }
-        println!();
-        self.try_fix_package(&cargo_toml_path, &unused_deps)?;
-        self.unused_dependencies += unused_deps.len();
         Ok(())
     }
 
-    /// Returns the remaining package dependency names.
-    fn shear_package(&mut self, metadata: &Metadata, package: &Package) -> Result<Deps> {
-        let workspace_root = metadata.workspace_root.as_std_path();
-        let dir = package
-            .manifest_path
-            .parent()
-            .ok_or_else(|| anyhow::anyhow!("failed to get parent path {}", &package.manifest_path))?
-            .as_std_path();
-        let relative_path = package
-            .manifest_path
-            .as_std_path()
-            .strip_prefix(workspace_root)
-            .unwrap_or(dir)
-            .to_string_lossy();
-
-        let mut ignored_package_names = Self::get_ignored_package_names(&package.metadata);
-        ignored_package_names.extend(Self::get_ignored_package_names(&metadata.workspace_metadata));
-
-        let this_package = metadata
-            .resolve
-            .as_ref()
-            .context("`cargo_metadata::MetadataCommand::no_deps` should not be called.")?
-            .nodes
-            .iter()
-            .find(|node| node.id == package.id)
-            .context("package should exist")?;
+    fn write_badge(&self, report: &Report) -> Result<()> {
+        let Some(path) = &self.options.badge else { return Ok(()) };
+        let badge = output::render_badge(report.unused_dependencies());
+        if path.as_os_str() == "-" {
+            print!("{badge}");
+        } else {
+            fs::write(path, badge)?;
+        }
+        Ok(())
+    }
 
-        let package_dependency_names_map = this_package
-            .deps // `deps` handles renamed dependencies whereas `dependencies` does not
-            .iter()
-            .map(|node_dep| {
-                Self::parse_package_id(&node_dep.pkg.repr)
-                    .map(|package_name| (node_dep.name.clone(), package_name))
-            })
-            .collect::<Result<Vec<_>>>()?
-            .into_iter()
-            .filter(|(_, name)| !ignored_package_names.contains(name.as_str()))
-            .collect::<HashMap<String, String>>();
+    fn print_summary(&self, report: &Report) {
+        println!("{}", report.coverage.summary());
 
-        let module_names_from_package_deps =
-            package_dependency_names_map.keys().cloned().collect::<HashSet<_>>();
+        if self.options.fix || self.options.fix_suppress {
+            let findings = report.packages.iter().flat_map(|package| &package.unused_dependencies);
+            let (mut skipped, mut failed) = (0, 0);
+            for finding in findings {
+                match finding.fix {
+                    Some(FixOutcome::Skipped(_)) => skipped += 1,
+                    Some(FixOutcome::Failed(_)) => failed += 1,
+                    _ => {}
+                }
+            }
+            if self.fixed_dependencies + skipped + failed > 0 {
+                let verb = if self.options.fix_suppress { "Ignored" } else { "Fixed" };
+                println!(
+                    "{verb} {} dependencies, skipped {skipped}, failed {failed}.",
+                    self.fixed_dependencies
+                );
+            }
+        }
 
-        let package_dependency_names =
-            package_dependency_names_map.values().cloned().collect::<HashSet<_>>();
+        if let Some(categories) = render_categories(&report.packages) {
+            println!("{categories}");
+        }
 
-        let module_names_from_rust_files = Self::get_package_dependencies_from_rust_files(package)?;
+        if !report.errors.is_empty() {
+            println!("Found {} dependency policy violations.", report.errors.len());
+        }
 
-        let unused_module_names = module_names_from_package_deps
-            .difference(&module_names_from_rust_files)
-            .collect::<Vec<_>>();
+        if self.suppressed_warnings > 0 {
+            println!("Suppressed {} warnings below `--min-severity`.", self.suppressed_warnings);
+        }
 
-        if unused_module_names.is_empty() {
-            return Ok(package_dependency_names);
+        if report.unused_dependencies() == 0 {
+            println!("No unused dependencies!");
+        } else if let Some(suppressions) = render_suppressions(&report.packages) {
+            print!(
+                "\n\
+                If you believe cargo-shear has detected an unused dependency incorrectly,\n\
+                you can ignore it in its Cargo.toml:\n\
+                \n\
+                {suppressions}"
+            );
         }
+    }
 
-        let unused_dependency_names = unused_module_names
-            .into_iter()
-            .map(|name| package_dependency_names_map[name].clone())
-            .collect::<Vec<_>>();
+    /// Prints and records the findings of `package`, with the location of the table and the
+    /// reason if they are reported as a whole table. Findings of members outside of
+    /// `default-members` are marked, since teams often triage those differently.
+    fn report_package(
+        &mut self,
+        package: &Package,
+        relative_path: &str,
+        findings: Vec<Finding>,
+        table: Option<(&str, &str)>,
+    ) {
+        let default_member = !self.non_default_members.contains(&package.id);
+        let note = if default_member { "" } else { " (not a default member)" };
+        let title = match table {
+            Some((location, reason)) => {
+                format!("{} -- {relative_path}{location}{note}: {reason}", package.name)
+            }
+            None => format!("{} -- {relative_path}{note}", package.name),
+        };
+        self.push_report(
+            &title,
+            PackageReport {
+                name: package.name.clone(),
+                manifest_path: relative_path.to_string(),
+                unused_dependencies: findings,
+                reason: table.map(|(_, reason)| reason.to_string()),
+                default_member,
+                workspace: false,
+            },
+        );
+    }
 
-        self.try_fix_package(package.manifest_path.as_std_path(), &unused_dependency_names)?;
+    /// Records `report`, printing its findings under `title`, or as they are found with
+    /// `--format json-lines`.
+    fn push_report(&mut self, title: &str, report: PackageReport) {
+        self.print_findings(title, &report);
+        self.reports.push(report);
+    }
 
-        if !unused_dependency_names.is_empty() {
-            self.unused_dependencies += unused_dependency_names.len();
-            println!("{} -- {relative_path}:", package.name);
-            for unused_dep in &unused_dependency_names {
-                println!("  {unused_dep}");
+    fn print_findings(&self, title: &str, report: &PackageReport) {
+        if self.quiet {
+            return;
+        }
+        if self.options.format == Format::JsonLines {
+            for finding in &report.unused_dependencies {
+                let line = JsonLine::UnusedDependency {
+                    package: &report.name,
+                    manifest_path: &report.manifest_path,
+                    reason: report.reason.as_deref(),
+                    default_member: report.default_member,
+                    workspace: report.workspace,
+                    finding,
+                };
+                println!("{line}");
             }
-            println!();
+            return;
         }
-
-        let package_dependency_names = package_dependency_names
-            .difference(&HashSet::from_iter(unused_dependency_names))
-            .cloned()
-            .collect::<Deps>();
-        Ok(package_dependency_names)
+        if self.options.format != Format::Human {
+            return;
+        }
+        println!("{title}:");
+        for finding in &report.unused_dependencies {
+            match &finding.fix {
+                Some(FixOutcome::Suppressed | FixOutcome::Skipped(_) | FixOutcome::Failed(_)) => {
+                    println!("  {} ({})", finding.dependency, finding.status());
+                }
+                _ => println!("  {}", finding.dependency),
+            }
+        }
+        println!();
     }
 
-    fn parse_package_id(s: &str) -> Result<String> {
-        // The node id can have multiple representations:
-        if s.contains(' ') {
-            // < Rust 1.77 : `memchr 2.7.1 (registry+https://github.com/rust-lang/crates.io-index)`
-            s.split(' ')
-                .next()
-                .map(ToString::to_string)
-                .ok_or_else(|| anyhow::anyhow!("{s} should have a space"))
+    /// Progress messages go to stderr for machine readable formats.
+    fn status(&self, message: &str) {
+        if self.quiet {
+            return;
+        }
+        if self.options.format == Format::Human {
+            println!("{message}");
+            println!();
         } else {
-            // >= Rust 1.77: `registry+https://github.com/rust-lang/crates.io-index#memchr@2.7.1`
-            PackageIdSpec::parse(s)
-                .map(|id| id.name().to_string())
-                .map_err(|err| anyhow::anyhow!(err))
+            eprintln!("{message}");
         }
     }
 
-    fn get_ignored_package_names(value: &serde_json::Value) -> HashSet<&str> {
-        value
-            .as_object()
-            .and_then(|object| object.get("cargo-shear"))
-            .and_then(|object| object.get("ignored"))
-            .and_then(|ignored| ignored.as_array())
-            .map(|ignored| ignored.iter().filter_map(|item| item.as_str()).collect::<HashSet<_>>())
-            .unwrap_or_default()
+    fn print_completions(shell: Shell) -> ExitCode {
+        let style = match shell {
+            Shell::Bash => "--bpaf-complete-style-bash",
+            Shell::Zsh => "--bpaf-complete-style-zsh",
+            Shell::Fish => "--bpaf-complete-style-fish",
+            Shell::Elvish => "--bpaf-complete-style-elvish",
+        };
+        // bpaf prints the completion script and exits when it sees the style flag.
+        let _ = cargo_shear_options().run_inner(Args::from(&[style][..]).set_name("cargo-shear"));
+        ExitCode::SUCCESS
     }
 
-    fn get_package_dependencies_from_rust_files(package: &Package) -> Result<Deps> {
-        Ok(Self::get_package_rust_files(package)
-            .par_iter()
-            .map(|path| Self::process_rust_source(path))
-            .collect::<Result<Vec<Deps>>>()?
-            .into_iter()
-            .fold(HashSet::new(), |a, b| a.union(&b).cloned().collect()))
+    fn run_restore(path: &Path, backup_dir: Option<&Path>) -> ExitCode {
+        match backup::restore(path, backup_dir) {
+            Ok(restored) if restored.is_empty() => {
+                println!("No backups found in {}", backup_dir.unwrap_or(path).to_string_lossy());
+                ExitCode::SUCCESS
+            }
+            Ok(restored) => {
+                for manifest_path in &restored {
+                    println!("Restored {}", manifest_path.to_string_lossy());
+                }
+                ExitCode::SUCCESS
+            }
+            Err(err) => {
+                println!("{err}");
+                ExitStatus::Error.into()
+            }
+        }
     }
 
-    fn get_package_rust_files(package: &Package) -> Vec<PathBuf> {
-        package
-            .targets
+    fn run_check_config(path: &Path) -> ExitCode {
+        println!("Checking configuration in {}", path.to_string_lossy());
+        println!();
+
+        match Self::check_config(path) {
+            Ok(0) => {
+                println!("No configuration problems!");
+                ExitCode::SUCCESS
+            }
+            Ok(problems) => {
+                println!("Found {problems} configuration problems.");
+                ExitStatus::ConfigError.into()
+            }
+            Err(err) => {
+                println!("{err}");
+                ExitStatus::Error.into()
+            }
+        }
+    }
+
+    /// Returns the number of problems found in the `cargo-shear` metadata tables.
+    fn check_config(path: &Path) -> Result<usize> {
+        let metadata = MetadataCommand::new().current_dir(path).exec()?;
+        let workspace_root = metadata.workspace_root.as_std_path();
+
+        let mut all_dependencies = vec![];
+        let mut all_import_names = HashSet::new();
+        let mut problems = 0;
+        for package in metadata.workspace_packages() {
+            let dependencies = package.dependencies.iter().collect::<Vec<_>>();
+            // The names the dependencies are imported as, which depend on their `[lib]` names.
+            let import_names = metadata
+                .resolve
+                .iter()
+                .flat_map(|resolve| &resolve.nodes)
+                .filter(|node| node.id == package.id)
+                .flat_map(|node| node.deps.iter().map(|dep| dep.name.clone()))
+                .collect::<HashSet<_>>();
+            let config = ShearConfig::from_metadata(&package.metadata);
+            let relative_path = package
+                .manifest_path
+                .as_std_path()
+                .strip_prefix(workspace_root)
+                .unwrap_or(package.manifest_path.as_std_path())
+                .to_string_lossy();
+            let manifest = fs::read_to_string(&package.manifest_path)?;
+            let mut messages = config.unknown_key_messages(&relative_path, &manifest, "package");
+            messages.extend(Self::config_problems(&config, &dependencies, &import_names));
+            let title = format!("{} -- {relative_path}", package.name);
+            problems += Self::report_config_problems(&title, &messages);
+            all_dependencies.extend(dependencies);
+            all_import_names.extend(import_names);
+        }
+
+        let config = ShearConfig::from_metadata(&metadata.workspace_metadata);
+        let manifest = fs::read_to_string(workspace_root.join("Cargo.toml"))?;
+        let mut messages = config.unknown_key_messages("Cargo.toml", &manifest, "workspace");
+        messages.extend(Self::config_problems(&config, &all_dependencies, &all_import_names));
+        problems += Self::report_config_problems("root -- Cargo.toml", &messages);
+
+        Ok(problems)
+    }
+
+    /// Values of the wrong type and ignored names which are not dependencies.
+    fn config_problems(
+        config: &ShearConfig,
+        dependencies: &[&Dependency],
+        import_names: &HashSet<String>,
+    ) -> Vec<String> {
+        let mut messages = config.errors.clone();
+        let dependency_names =
+            dependencies.iter().map(|dep| dep.name.as_str()).collect::<HashSet<_>>();
+        // Each form `ignored` accepts, see `ShearConfig::ignored_entry`.
+        let ignorable_names = dependencies
             .iter()
-            .flat_map(|target| {
-                if target.kind.iter().any(|s| *s == TargetKind::CustomBuild) {
-                    vec![target.src_path.clone().into_std_path_buf()]
-                } else {
-                    let target_dir = target.src_path.parent().unwrap_or_else(|| {
-                        panic!("failed to get parentp path {}", &target.src_path)
-                    });
-                    WalkDir::new(target_dir)
-                        .into_iter()
-                        .filter_map(Result::ok)
-                        .filter(|e| e.path().extension().is_some_and(|ext| ext == "rs"))
-                        .map(DirEntry::into_path)
-                        .collect::<Vec<_>>()
+            .flat_map(|dep| {
+                let key = dep.rename.as_ref().unwrap_or(&dep.name);
+                [dep.name.clone(), key.clone(), key.replace('-', "_")]
+            })
+            .chain(import_names.iter().cloned())
+            .collect::<HashSet<_>>();
+        let mut unknown_ignored = config
+            .ignored
+            .iter()
+            .filter(|name| !ignorable_names.contains(**name))
+            .collect::<Vec<_>>();
+        unknown_ignored.sort();
+        for name in unknown_ignored {
+            messages.push(format!(
+                "`{name}` in `ignored` is not the package name, dependency key or import name of a dependency"
+            ));
+        }
+        let mut unknown_aliases = config
+            .aliases
+            .iter()
+            .filter(|(_, dependency)| !dependency_names.contains(*dependency))
+            .collect::<Vec<_>>();
+        unknown_aliases.sort();
+        for (alias, dependency) in unknown_aliases {
+            messages.push(format!("`{dependency}` in `aliases.{alias}` is not a dependency"));
+        }
+        let mut unknown_link_only = config
+            .link_only
+            .keys()
+            .filter(|name| !dependency_names.contains(*name))
+            .collect::<Vec<_>>();
+        unknown_link_only.sort();
+        for name in unknown_link_only {
+            messages.push(format!("`{name}` in `link-only` is not a dependency"));
+        }
+        messages
+    }
+
+    fn report_config_problems(title: &str, messages: &[String]) -> usize {
+        if !messages.is_empty() {
+            println!("{title}:");
+            for message in messages {
+                println!("  {message}");
+            }
+            println!();
+        }
+        messages.len()
+    }
+
+    /// Rejects options which cannot be used together, before anything is analyzed or written.
+    fn validate_options(&self) -> Result<()> {
+        let options = &self.options;
+        if options.fix && options.fix_suppress {
+            anyhow::bail!("`--fix` and `--fix-suppress` cannot be used together");
+        }
+        if options.no_workspace && options.workspace_only {
+            anyhow::bail!("`--no-workspace` and `--workspace-only` cannot be used together");
+        }
+        if options.commit.is_some() && (!options.fix || options.emit_patch.is_some()) {
+            anyhow::bail!("`--commit` requires `--fix` and cannot be used with `--emit-patch`");
+        }
+        let writes = options.fix || options.fix_suppress || options.emit_patch.is_some();
+        if writes && options.compare_ref.is_some() {
+            anyhow::bail!(
+                "`--compare-ref` cannot be used with `--fix`, `--fix-suppress` or `--emit-patch`"
+            );
+        }
+        if writes && options.deterministic {
+            anyhow::bail!(
+                "`--deterministic` cannot be used with `--fix`, `--fix-suppress` or `--emit-patch`"
+            );
+        }
+        Ok(())
+    }
+
+    fn shear(&mut self) -> Result<()> {
+        self.validate_options()?;
+        let metadata = match self.cargo_metadata() {
+            Ok(metadata) => metadata,
+            Err(err) if self.options.best_effort => return self.shear_best_effort(&err),
+            Err(err) => return Err(err.into()),
+        };
+        let workspace_root = metadata.workspace_root.as_std_path();
+
+        if self.options.fix || self.options.fix_suppress {
+            self.record_manifest_hashes(&metadata)?;
+            if !self.options.allow_dirty && self.options.emit_patch.is_none() {
+                Self::check_clean_manifests(&metadata)?;
+            }
+        }
+
+        if self.options.commit.is_some() {
+            let staged_files = git::staged_files(workspace_root)?;
+            if !staged_files.is_empty() {
+                anyhow::bail!(
+                    "Refusing to commit with changes already staged:\n  {}",
+                    staged_files.join("\n  ")
+                );
+            }
+        }
+
+        if let Some(rev) = &self.options.compare_ref {
+            self.pre_existing = self.unused_at_rev(workspace_root, rev)?;
+        }
+
+        self.validate_config(
+            &ShearConfig::from_metadata(&metadata.workspace_metadata),
+            metadata.workspace_root.join("Cargo.toml").as_std_path(),
+            "Cargo.toml",
+            "workspace",
+        )?;
+
+        let members = metadata.workspace_packages();
+        // Cargo before 1.71 does not report the default members.
+        if !workspace_default_members_is_missing(&metadata.workspace_default_members) {
+            self.non_default_members = members
+                .iter()
+                .map(|package| package.id.clone())
+                .filter(|id| !metadata.workspace_default_members.contains(id))
+                .collect();
+        } else if self.options.default_members {
+            anyhow::bail!("`--default-members` requires Cargo 1.71 or newer");
+        }
+        // A lone crate at the workspace root has no workspace dependencies or other members to
+        // select from, so it skips straight to its own analysis.
+        let single_crate = matches!(
+            members.as_slice(),
+            [package] if package.manifest_path.parent() == Some(metadata.workspace_root.as_path())
+        ) && self.options.package.is_empty()
+            && self.options.exclude.is_empty();
+        let mut package_dependencies = HashSet::new();
+        let mut stopped = false;
+        if single_crate && !self.options.workspace_only {
+            let start = Instant::now();
+            self.shear_package(&metadata, members[0])?;
+            self.parse_profile.packages.push((members[0].name.clone(), start.elapsed()));
+            self.coverage.analyzed += 1;
+            self.warn_unused_reexports(&metadata, &members)?;
+        } else if self.options.workspace_only {
+            self.coverage.skip("workspace_only", members.len());
+            for package in members {
+                package_dependencies.extend(Self::declared_dependencies(&metadata, package)?);
+            }
+        } else {
+            stopped = self.shear_members(&metadata, &members, &mut package_dependencies)?;
+        }
+
+        // After `--fail-fast` stops, the fixes made so far are still written out.
+        if !stopped {
+            self.warn_duplicate_versions(&metadata)?;
+            self.warn_inconsistent_renames(&metadata)?;
+            if !self.options.no_workspace && !single_crate {
+                self.shear_workspace(&metadata, &package_dependencies)?;
+            }
+        }
+        if self.options.profile_parse {
+            self.status(&self.parse_profile.render(20));
+        }
+        self.format_manifests()?;
+        self.write_patch()?;
+        self.commit_fixes(workspace_root)
+    }
+
+    /// `cargo metadata` with all features, from the cache with `--metadata-cache` while it is valid.
+    fn cargo_metadata(&self) -> cargo_metadata::Result<Metadata> {
+        let cache_path =
+            self.options.metadata_cache.then(|| metadata_cache::cache_path(&self.options.path));
+        if let Some(metadata) = cache_path
+            .as_deref()
+            .and_then(|cache_path| metadata_cache::load(cache_path, &self.options.cargo_args))
+        {
+            return Ok(metadata);
+        }
+        let metadata = MetadataCommand::new()
+            .features(CargoOpt::AllFeatures)
+            .current_dir(&self.options.path)
+            .other_options(self.options.cargo_args.clone())
+            .exec()?;
+        if let Some(cache_path) = &cache_path {
+            metadata_cache::store(cache_path, &self.options.cargo_args, &metadata);
+        }
+        Ok(metadata)
+    }
+
+    /// Analyzes the selected workspace members, collecting the workspace dependencies they use.
+    ///
+    /// Returns whether `--fail-fast` stopped at a package with unused dependencies.
+    fn shear_members(
+        &mut self,
+        metadata: &Metadata,
+        members: &[&Package],
+        package_dependencies: &mut HashSet<String>,
+    ) -> Result<bool> {
+        let selected = self.select_members(members.to_vec())?;
+        let selected_count = selected.len();
+        for &package in &selected {
+            let start = Instant::now();
+            let deps = self.shear_package(metadata, package)?;
+            package_dependencies.extend(deps);
+            self.parse_profile.packages.push((package.name.clone(), start.elapsed()));
+            self.coverage.analyzed += 1;
+
+            if self.options.fail_fast && self.unused_dependencies > 0 {
+                self.coverage.skip("not_reached", selected_count - self.coverage.analyzed);
+                self.status(
+                    "Stopping at the first package with unused dependencies (`--fail-fast`).",
+                );
+                return Ok(true);
+            }
+        }
+        self.warn_unused_reexports(metadata, &selected)?;
+        // Members which are not analyzed may still use the workspace dependencies they declare.
+        for package in members.iter().filter(|package| !selected.contains(package)) {
+            package_dependencies.extend(Self::declared_dependencies(metadata, package)?);
+        }
+        Ok(false)
+    }
+
+    /// The workspace members selected by `--package` and `--exclude`; the others are counted as
+    /// excluded.
+    fn select_members<'a>(&mut self, members: Vec<&'a Package>) -> Result<Vec<&'a Package>> {
+        let include = Self::parse_package_specs(&self.options.package)?;
+        let exclude = Self::parse_package_specs(&self.options.exclude)?;
+        for spec in &include {
+            if !members.iter().any(|package| spec.matches(package)) {
+                let near_misses =
+                    spec.near_misses(members.iter().map(|package| package.name.as_str()));
+                if near_misses.is_empty() {
+                    anyhow::bail!("package `{spec}` matches no workspace member");
+                }
+                anyhow::bail!(
+                    "package `{spec}` matches no workspace member, did you mean `{}`?",
+                    near_misses.join("`, `")
+                );
+            }
+        }
+
+        let (selected, excluded): (Vec<_>, Vec<_>) = members.into_iter().partition(|package| {
+            !exclude.iter().any(|spec| spec.matches(package))
+                && (include.is_empty() || include.iter().any(|spec| spec.matches(package)))
+        });
+        if selected.is_empty() && !excluded.is_empty() {
+            let mut names =
+                excluded.iter().map(|package| package.name.as_str()).collect::<Vec<_>>();
+            names.sort_unstable();
+            anyhow::bail!(
+                "`--package` and `--exclude` select none of the workspace members: `{}`",
+                names.join("`, `")
+            );
+        }
+        self.coverage.skip("excluded", excluded.len());
+        if !self.options.default_members {
+            return Ok(selected);
+        }
+        let (selected, not_default): (Vec<_>, Vec<_>) = selected
+            .into_iter()
+            .partition(|package| !self.non_default_members.contains(&package.id));
+        self.coverage.skip("not_default", not_default.len());
+        Ok(selected)
+    }
+
+    /// Degraded mode for `--best-effort`: without `cargo metadata` dependencies are read from the
+    /// manifests and sources are found by walking the package directories, so the results are
+    /// approximate and never fixed.
+    fn shear_best_effort(&mut self, err: &cargo_metadata::Error) -> Result<()> {
+        self.warn(&[format!(
+            "`cargo metadata` failed, reading the manifests directly instead; \
+             results are approximate and will not be fixed\n{err}"
+        )]);
+
+        let include = Self::parse_package_specs(&self.options.package)?;
+        let exclude = Self::parse_package_specs(&self.options.exclude)?;
+        let root = self.options.path.clone();
+        let dirs = discovery::package_dirs(&root, self.file_provider.as_ref())?;
+        let total = dirs.len();
+        for (index, dir) in dirs.into_iter().enumerate() {
+            let manifest_path = dir.join("Cargo.toml");
+            let analysis = match analyze_manifest(
+                &manifest_path,
+                &discovery::package_rust_files(&dir, self.file_provider.as_ref()),
+                self.file_provider.as_ref(),
+            ) {
+                Ok(analysis) => analysis,
+                Err(err) => {
+                    self.coverage.skip("failed", 1);
+                    self.warn(&[format!("skipping {}: {err}", manifest_path.display())]);
+                    continue;
+                }
+            };
+            self.warn_manifest_fields(&manifest_path, &analysis.warnings);
+
+            if exclude.iter().any(|spec| spec.matches_name(&analysis.package_name))
+                || (!include.is_empty()
+                    && !include.iter().any(|spec| spec.matches_name(&analysis.package_name)))
+            {
+                self.coverage.skip("excluded", 1);
+                continue;
+            }
+            self.coverage.analyzed += 1;
+            if analysis.unused_dependencies.is_empty() {
+                continue;
+            }
+
+            let relative_path =
+                manifest_path.strip_prefix(&root).unwrap_or(&manifest_path).to_string_lossy();
+            self.unused_dependencies += analysis.unused_dependencies.len();
+            let findings =
+                analysis.unused_dependencies.into_iter().map(Finding::new).collect::<Vec<_>>();
+            let title = format!("{} -- {relative_path} (approximate)", analysis.package_name);
+            self.push_report(
+                &title,
+                PackageReport {
+                    name: format!("{} (approximate)", analysis.package_name),
+                    manifest_path: relative_path.to_string(),
+                    unused_dependencies: findings,
+                    reason: None,
+                    default_member: true,
+                    workspace: false,
+                },
+            );
+
+            if self.options.fail_fast {
+                self.coverage.skip("not_reached", total - index - 1);
+                self.status(
+                    "Stopping at the first package with unused dependencies (`--fail-fast`).",
+                );
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// `(package name, dependency)` pairs which are unused at `rev` and declared in its manifests.
+    fn unused_at_rev(
+        &self,
+        workspace_root: &Path,
+        rev: &str,
+    ) -> Result<HashSet<(Option<String>, String)>> {
+        let file_provider = git::GitFileProvider::new(workspace_root, rev)?;
+        let mut options = self.read_only_options();
+        options.compare_ref = None;
+        options.fail_fast = false;
+        options.badge = None;
+        options.min_severity = Severity::Error;
+        let mut baseline = Self::new(options).with_file_provider(file_provider);
+        baseline.quiet = true;
+        baseline.shear()?;
+
+        let mut pre_existing = HashSet::new();
+        for report in &baseline.reports {
+            let manifest = baseline
+                .file_provider
+                .read_to_string(&workspace_root.join(&report.manifest_path))?;
+            let Ok(manifest) = cargo_toml::Manifest::from_str(&manifest) else { continue };
+            let declared = manifest
+                .dependencies
+                .iter()
+                .chain(&manifest.dev_dependencies)
+                .chain(&manifest.build_dependencies)
+                .chain(manifest.workspace.iter().flat_map(|workspace| &workspace.dependencies))
+                .map(|(key, dependency)| {
+                    dependency.detail().and_then(|detail| detail.package.as_ref()).unwrap_or(key)
+                })
+                .collect::<HashSet<_>>();
+            pre_existing.extend(
+                report
+                    .unused_dependencies
+                    .iter()
+                    .filter(|finding| declared.contains(&finding.dependency))
+                    .map(|finding| {
+                        let package = (!report.workspace).then(|| report.name.clone());
+                        (package, finding.dependency.clone())
+                    }),
+            );
+        }
+        self.status(&format!("Reporting only unused dependencies introduced since `{rev}`."));
+        Ok(pre_existing)
+    }
+
+    /// The manifests of the workspace members and of the workspace root.
+    fn workspace_manifest_paths(metadata: &Metadata) -> Vec<PathBuf> {
+        metadata
+            .workspace_packages()
+            .into_iter()
+            .map(|package| package.manifest_path.as_std_path().to_path_buf())
+            .chain([metadata.workspace_root.join("Cargo.toml").into_std_path_buf()])
+            .collect()
+    }
+
+    fn record_manifest_hashes(&mut self, metadata: &Metadata) -> Result<()> {
+        for manifest_path in Self::workspace_manifest_paths(metadata) {
+            let hash = content_hash(&self.file_provider.read_to_string(&manifest_path)?);
+            self.manifest_hashes.insert(manifest_path, hash);
+        }
+        Ok(())
+    }
+
+    /// Like `cargo fix`, refuses to edit manifests with uncommitted changes, which could not be told
+    /// apart from the fixes, unless `--allow-dirty` is given. Manifests outside of git are edited.
+    fn check_clean_manifests(metadata: &Metadata) -> Result<()> {
+        let workspace_root = metadata.workspace_root.as_std_path();
+        if !git::is_work_tree(workspace_root) {
+            return Ok(());
+        }
+        let dirty_files =
+            git::uncommitted_files(workspace_root, &Self::workspace_manifest_paths(metadata))?;
+        if !dirty_files.is_empty() {
+            anyhow::bail!(
+                "Refusing to fix manifests with uncommitted changes, commit or stash them first \
+                 or pass `--allow-dirty`:\n  {}",
+                dirty_files.join("\n  ")
+            );
+        }
+        Ok(())
+    }
+
+    /// Runs `--format-manifest` with each modified manifest path appended to the command.
+    fn format_manifests(&self) -> Result<()> {
+        let Some(command) = &self.options.format_manifest else { return Ok(()) };
+        let mut words = command.split_whitespace();
+        let program = words.next().context("`--format-manifest` command is empty")?;
+        let args = words.collect::<Vec<_>>();
+        for manifest_path in &self.fixed_manifests {
+            let status = process::Command::new(program)
+                .args(&args)
+                .arg(manifest_path)
+                .status()
+                .with_context(|| format!("failed to run `{command}`"))?;
+            if !status.success() {
+                anyhow::bail!("`{command}` failed on {} with {status}", manifest_path.display());
+            }
+        }
+        Ok(())
+    }
+
+    fn commit_fixes(&self, workspace_root: &Path) -> Result<()> {
+        let Some(message) = &self.options.commit else { return Ok(()) };
+        if self.fixed_manifests.is_empty() {
+            return Ok(());
+        }
+        git::commit(workspace_root, &self.fixed_manifests, message)?;
+        self.status(&format!("Committed {} manifests: {message}", self.fixed_manifests.len()));
+        Ok(())
+    }
+
+    fn write_patch(&self) -> Result<()> {
+        let Some(patch_path) = &self.options.emit_patch else { return Ok(()) };
+        fs::write(patch_path, &self.patch)?;
+        self.status(&format!("Wrote fixes to {}", patch_path.to_string_lossy()));
+        Ok(())
+    }
+
+    /// Crates which workspace members depend on with incompatible requirements, so that several
+    /// versions of them are built.
+    fn warn_duplicate_versions(&mut self, metadata: &Metadata) -> Result<()> {
+        let Some(resolve) = &metadata.resolve else { return Ok(()) };
+        let workspace_root = metadata.workspace_root.as_std_path();
+        // package name -> version -> declarations
+        let mut versions = BTreeMap::<&str, BTreeMap<&semver::Version, Vec<String>>>::new();
+        for member in metadata.workspace_packages() {
+            let Some(node) = resolve.nodes.iter().find(|node| node.id == member.id) else {
+                continue;
+            };
+            let relative_path = member
+                .manifest_path
+                .as_std_path()
+                .strip_prefix(workspace_root)
+                .unwrap_or(member.manifest_path.as_std_path())
+                .to_string_lossy();
+            let manifest = self.file_provider.read_to_string(member.manifest_path.as_std_path())?;
+            for node_dep in &node.deps {
+                if metadata.workspace_members.contains(&node_dep.pkg) {
+                    continue;
+                }
+                let dependency = &metadata[&node_dep.pkg];
+                let name = dependency.name.as_str();
+                let location = manifest_edit::dependency_location(&manifest, name).map_or_else(
+                    || relative_path.to_string(),
+                    |location| format!("{relative_path}:{location}"),
+                );
+                let requirements = member
+                    .dependencies
+                    .iter()
+                    .filter(|dep| dep.name == name)
+                    .map(|dep| format!("`{}`", dep.req))
+                    .collect::<BTreeSet<_>>()
+                    .into_iter()
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                versions
+                    .entry(name)
+                    .or_default()
+                    .entry(&dependency.version)
+                    .or_default()
+                    .push(format!("`{}` ({location}) requires {requirements}", member.name));
+            }
+        }
+
+        let messages = versions
+            .into_iter()
+            .filter(|(_, versions)| versions.len() > 1)
+            .map(|(name, versions)| {
+                let declarations = versions
+                    .into_iter()
+                    .map(|(version, declarations)| {
+                        format!("{version} for {}", declarations.join(", "))
+                    })
+                    .collect::<Vec<_>>()
+                    .join("; ");
+                format!(
+                    "`{name}` is built in several versions for workspace members: {declarations}"
+                )
+            })
+            .collect::<Vec<_>>();
+        self.warn(&messages);
+        Ok(())
+    }
+
+    /// Warns about external packages which workspace members import under different names, e.g.
+    /// `serde_v1` in one member and `serde1` in another.
+    fn warn_inconsistent_renames(&mut self, metadata: &Metadata) -> Result<()> {
+        let Some(resolve) = &metadata.resolve else { return Ok(()) };
+        let workspace_root = metadata.workspace_root.as_std_path();
+        // package id -> import name -> declarations
+        let mut names = BTreeMap::<&PackageId, BTreeMap<&str, Vec<String>>>::new();
+        for member in metadata.workspace_packages() {
+            let Some(node) = resolve.nodes.iter().find(|node| node.id == member.id) else {
+                continue;
+            };
+            let relative_path = member
+                .manifest_path
+                .as_std_path()
+                .strip_prefix(workspace_root)
+                .unwrap_or(member.manifest_path.as_std_path())
+                .to_string_lossy();
+            let manifest = self.file_provider.read_to_string(member.manifest_path.as_std_path())?;
+            for node_dep in &node.deps {
+                if metadata.workspace_members.contains(&node_dep.pkg) {
+                    continue;
+                }
+                let name = metadata[&node_dep.pkg].name.as_str();
+                let location = manifest_edit::dependency_location(&manifest, name).map_or_else(
+                    || relative_path.to_string(),
+                    |location| format!("{relative_path}:{location}"),
+                );
+                names
+                    .entry(&node_dep.pkg)
+                    .or_default()
+                    .entry(&node_dep.name)
+                    .or_default()
+                    .push(format!("`{}` ({location})", member.name));
+            }
+        }
+
+        let messages = names
+            .into_iter()
+            .filter(|(_, names)| names.len() > 1)
+            .map(|(id, names)| {
+                let declarations = names
+                    .into_iter()
+                    .map(|(name, declarations)| format!("`{name}` in {}", declarations.join(", ")))
+                    .collect::<Vec<_>>()
+                    .join("; ");
+                format!(
+                    "`{}` is imported under different names by workspace members: {declarations}",
+                    metadata[id].name
+                )
+            })
+            .collect::<Vec<_>>();
+        self.warn(&messages);
+        Ok(())
+    }
+
+    /// Warns about the fields of the manifest at `manifest_path` which [`manifest::parse`] ignored.
+    fn warn_manifest_fields(&mut self, manifest_path: &Path, warnings: &[String]) {
+        self.warn(
+            &warnings
+                .iter()
+                .map(|warning| format!("{}: {warning}", manifest_path.display()))
+                .collect::<Vec<_>>(),
+        );
+    }
+
+    fn shear_workspace(
+        &mut self,
+        workspace_metadata: &Metadata,
+        all_pkg_deps: &Deps,
+    ) -> Result<()> {
+        if workspace_metadata.workspace_packages().len() <= 1 {
+            return Ok(());
+        }
+        let metadata_path = workspace_metadata.workspace_root.as_std_path();
+        let cargo_toml_path = metadata_path.join("Cargo.toml");
+        let (metadata, warnings) =
+            manifest::parse(&self.file_provider.read_to_string(&cargo_toml_path)?)?;
+        self.warn_manifest_fields(&cargo_toml_path, &warnings);
+        let Some(workspace) = &metadata.workspace else { return Ok(()) };
+        // The manifest of a root package is checked with the package.
+        if metadata.package.is_none() {
+            self.lint_manifest(workspace_metadata, None, "Cargo.toml")?;
+        }
+
+        let config = ShearConfig::from_metadata(&workspace_metadata.workspace_metadata);
+
+        let workspace_deps = workspace
+            .dependencies
+            .iter()
+            .filter(|(_, dependency)| {
+                !config.ignore_sources.contains(source_kind(declared_source(dependency)))
+            })
+            .map(|(key, dependency)| {
+                // renamed package, e.g. `ustr = { package = "ustr-fxhash", version = "1.0.0" }`
+                let name = dependency.detail().and_then(|detail| detail.package.as_ref());
+                (key, name.unwrap_or(key))
+            })
+            .filter(|(key, name)| config.ignored_entry(key, name, &key.replace('-', "_")).is_none())
+            .map(|(_, name)| name.clone())
+            .collect::<HashSet<String>>();
+
+        let mut unused_deps = workspace_deps
+            .difference(all_pkg_deps)
+            .filter(|name| !self.pre_existing.contains(&(None, (*name).clone())))
+            .cloned()
+            .collect::<Vec<_>>();
+        unused_deps.sort();
+
+        if unused_deps.is_empty() {
+            return Ok(());
+        }
+
+        let path = cargo_toml_path
+            .strip_prefix(env::current_dir()?)
+            .unwrap_or(&cargo_toml_path)
+            .to_string_lossy();
+        let findings = self.try_fix_package(
+            &cargo_toml_path,
+            "Cargo.toml",
+            &unused_deps,
+            FixKind::UnusedWorkspaceDependency,
+            &HashMap::new(),
+        )?;
+        self.unused_dependencies += unused_deps.len();
+        self.push_report(
+            &format!("root -- {path}"),
+            PackageReport {
+                name: "root".to_string(),
+                manifest_path: "Cargo.toml".to_string(),
+                unused_dependencies: findings,
+                reason: None,
+                default_member: true,
+                workspace: true,
+            },
+        );
+        Ok(())
+    }
+
+    /// Returns the remaining package dependency names.
+    fn shear_package(&mut self, metadata: &Metadata, package: &Package) -> Result<Deps> {
+        let workspace_root = metadata.workspace_root.as_std_path();
+        let dir = package
+            .manifest_path
+            .parent()
+            .ok_or_else(|| anyhow::anyhow!("failed to get parent path {}", &package.manifest_path))?
+            .as_std_path();
+        let relative_path = package
+            .manifest_path
+            .as_std_path()
+            .strip_prefix(workspace_root)
+            .unwrap_or(dir)
+            .to_string_lossy();
+
+        let mut config = ShearConfig::from_metadata(&package.metadata);
+        self.validate_config(
+            &config,
+            package.manifest_path.as_std_path(),
+            &relative_path,
+            "package",
+        )?;
+        config.inherit(ShearConfig::from_metadata(&metadata.workspace_metadata));
+
+        let this_package = metadata
+            .resolve
+            .as_ref()
+            .context("`cargo_metadata::MetadataCommand::no_deps` should not be called.")?
+            .nodes
+            .iter()
+            .find(|node| node.id == package.id)
+            .context("package should exist")?;
+
+        self.warn_shadowed_members(metadata, package, this_package, &relative_path)?;
+        self.check_policies(metadata, package, this_package, &relative_path, &config)?;
+        self.warn_dependency_budget(metadata, package, this_package, &relative_path, &config)?;
+        let skip_dev_dependencies = self.options.skip_conventional_aux
+            && Self::is_conventional_aux(package, &relative_path);
+        if !skip_dev_dependencies {
+            self.warn_shadowed_dev_dependencies(package, &relative_path);
+        }
+        self.lint_manifest(metadata, Some(package), &relative_path)?;
+
+        let mut package_dependency_names_map = Self::dependency_names_map(this_package, &config)?;
+        // Ignored dependencies still need their `[workspace.dependencies]` entries.
+        let mut package_dependency_names =
+            package_dependency_names_map.values().cloned().collect::<HashSet<_>>();
+        let ignored =
+            Self::take_ignored_dependencies(package, &config, &mut package_dependency_names_map);
+
+        for name in self.shear_dependency_tables(
+            package,
+            this_package,
+            &relative_path,
+            skip_dev_dependencies,
+            &mut package_dependency_names_map,
+        )? {
+            package_dependency_names.remove(&name);
+        }
+
+        let mut imports = self.collect_package_imports(package, workspace_root, &config)?;
+        self.add_workspace_macro_crates(metadata, this_package, &mut imports)?;
+        self.warn_unused_derive_features(package, &relative_path, &imports.derive_crates)?;
+        self.warn_redundant_ignores(package, &relative_path, &ignored, &imports.deps);
+        self.warn_doc_only_dependencies(
+            package,
+            &relative_path,
+            &package_dependency_names_map,
+            &imports.doc_only_deps,
+        )?;
+        let unused_module_names = find_unused_module_names(
+            &package_dependency_names_map,
+            imports.deps,
+            &config.aliases,
+            &config.known_imports,
+        );
+
+        self.warn_feature_graph(package, &relative_path, &unused_module_names)?;
+
+        if unused_module_names.is_empty() {
+            return Ok(package_dependency_names);
+        }
+
+        let unused_dependency_names = self.unused_dependency_names(
+            package,
+            &relative_path,
+            &config,
+            &package_dependency_names_map,
+            unused_module_names,
+        )?;
+
+        let mut findings = self.try_fix_package(
+            package.manifest_path.as_std_path(),
+            &relative_path,
+            &unused_dependency_names,
+            FixKind::UnusedDependency,
+            &Self::feature_unification_risks(metadata, package, &unused_dependency_names),
+        )?;
+        Self::categorize_findings(metadata, package, this_package, &mut findings);
+
+        // Dependencies kept by `--fix` still need their `[workspace.dependencies]` entries.
+        let removed_dependency_names = findings
+            .iter()
+            .filter(|finding| finding.fix.is_none() || finding.is_fixed())
+            .map(|finding| finding.dependency.clone())
+            .collect::<Deps>();
+
+        if !unused_dependency_names.is_empty() {
+            self.unused_dependencies += unused_dependency_names.len();
+            self.report_package(package, &relative_path, findings, None);
+        }
+
+        let package_dependency_names = package_dependency_names
+            .difference(&removed_dependency_names)
+            .cloned()
+            .collect::<Deps>();
+        Ok(package_dependency_names)
+    }
+
+    /// Imports of all source files of `package`, recording their parse times.
+    fn collect_package_imports(
+        &mut self,
+        package: &Package,
+        workspace_root: &Path,
+        config: &ShearConfig,
+    ) -> Result<Imports> {
+        let (imports, parse_times) = collect_reachable_imports_timed(
+            self.file_provider.as_ref(),
+            &Self::get_package_roots(package),
+            self.options.max_parse_size,
+            &config.attribute_crate_refs,
+        )?;
+        self.parse_profile.files.extend(parse_times.into_iter().map(|(path, time)| {
+            (
+                path.strip_prefix(workspace_root).unwrap_or(&path).to_string_lossy().into_owned(),
+                time,
+            )
+        }));
+        self.member_usage.insert(package.id.clone(), MemberUsage::new(&imports));
+        Ok(imports)
+    }
+
+    /// The [`MemberUsage`] of the workspace member `id`, parsing it unless it was analyzed.
+    fn member_usage(&mut self, metadata: &Metadata, id: &PackageId) -> Result<&MemberUsage> {
+        if !self.member_usage.contains_key(id) {
+            let imports = collect_reachable_imports_timed(
+                self.file_provider.as_ref(),
+                &Self::get_package_roots(&metadata[id]),
+                self.options.max_parse_size,
+                &[],
+            )?
+            .0;
+            self.member_usage.insert(id.clone(), MemberUsage::new(&imports));
+        }
+        Ok(&self.member_usage[id])
+    }
+
+    /// Adds the [`Self::workspace_macro_crates`] of `node` to `imports`.
+    fn add_workspace_macro_crates(
+        &mut self,
+        metadata: &Metadata,
+        node: &Node,
+        imports: &mut Imports,
+    ) -> Result<()> {
+        let macro_crates = self.workspace_macro_crates(metadata, node, &imports.macro_calls)?;
+        imports.doc_only_deps.retain(|name| !macro_crates.contains(name));
+        imports.deps.extend(macro_crates);
+        Ok(())
+    }
+
+    /// Crates referenced by the exported macros of workspace members which `node` invokes:
+    /// paths like `::reqwest::Client` in a `macro_rules!` body resolve in the invoking crate.
+    fn workspace_macro_crates(
+        &mut self,
+        metadata: &Metadata,
+        node: &Node,
+        macro_calls: &Deps,
+    ) -> Result<Deps> {
+        let mut crates = Deps::new();
+        for node_dep in &node.deps {
+            if !metadata.workspace_members.contains(&node_dep.pkg) {
+                continue;
+            }
+            let macros = &self.member_usage(metadata, &node_dep.pkg)?.exported_macros;
+            for call in macro_calls {
+                // `a::client!()`, or `client!()` after `use a::client;` or `#[macro_use]`
+                let name = match call.rsplit_once("::") {
+                    Some((krate, name)) if krate == node_dep.name => name,
+                    Some(_) => continue,
+                    None => call.as_str(),
+                };
+                crates.extend(macros.get(name).into_iter().flatten().cloned());
+            }
+        }
+        Ok(crates)
+    }
+
+    /// Fails with a [`ConfigError`] on values of the wrong type, and warns about unknown keys.
+    fn validate_config(
+        &mut self,
+        config: &ShearConfig,
+        manifest_path: &Path,
+        display_path: &str,
+        root: &str,
+    ) -> Result<()> {
+        if !config.errors.is_empty() {
+            return Err(ConfigError(format!(
+                "Invalid `[{root}.metadata.cargo-shear]` in {display_path}:\n  {}",
+                config.errors.join("\n  ")
+            ))
+            .into());
+        }
+        if config.unknown_keys.is_empty() {
+            return Ok(());
+        }
+        let manifest = self.file_provider.read_to_string(manifest_path)?;
+        self.warn(&config.unknown_key_messages(display_path, &manifest, root));
+        Ok(())
+    }
+
+    /// Imports are matched by name, so an external `rand` cannot be told apart from a member `rand`.
+    fn warn_shadowed_members(
+        &mut self,
+        metadata: &Metadata,
+        package: &Package,
+        this_package: &Node,
+        relative_path: &str,
+    ) -> Result<()> {
+        let member_module_names = metadata
+            .workspace_packages()
+            .into_iter()
+            .map(|member| (member.name.replace('-', "_"), member.name.as_str()))
+            .collect::<HashMap<_, _>>();
+        let mut shadowing_warnings = Vec::new();
+        for node_dep in &this_package.deps {
+            if metadata.workspace_members.contains(&node_dep.pkg) {
+                continue;
+            }
+            if let Some(member_name) = member_module_names.get(&node_dep.name) {
+                let package_name = Self::parse_package_id(&node_dep.pkg.repr)?;
+                shadowing_warnings.push(format!(
+                    "dependency `{package_name}` of `{}` ({relative_path}) is an external package \
+                     sharing its name with workspace member `{member_name}`",
+                    package.name
+                ));
+            }
+        }
+        self.warn(&shadowing_warnings);
+        Ok(())
+    }
+
+    /// `banned` and `allowed-only` violations by the direct dependencies of `package`.
+    fn check_policies(
+        &mut self,
+        metadata: &Metadata,
+        package: &Package,
+        this_package: &Node,
+        relative_path: &str,
+        config: &ShearConfig,
+    ) -> Result<()> {
+        if config.banned.is_empty() && config.allowed_only.is_none() {
+            return Ok(());
+        }
+        let manifest = self.file_provider.read_to_string(package.manifest_path.as_std_path())?;
+        let mut errors = vec![];
+        for node_dep in &this_package.deps {
+            let dependency = &metadata[&node_dep.pkg];
+            let name = dependency.name.as_str();
+            let location = manifest_edit::dependency_location(&manifest, name).map_or_else(
+                || relative_path.to_string(),
+                |location| format!("{relative_path}:{location}"),
+            );
+            if let Some(banned) =
+                config.banned.iter().find(|banned| banned.matches(name, &dependency.version))
+            {
+                errors.push(format!(
+                    "`{}` ({location}) depends on `{name}` {}, which is banned by `{}`",
+                    package.name, dependency.version, banned.entry
+                ));
+            } else if config.allowed_only.as_ref().is_some_and(|allowed| !allowed.contains(name))
+                && !metadata.workspace_members.contains(&node_dep.pkg)
+            {
+                errors.push(format!(
+                    "`{}` ({location}) depends on `{name}`, which is not in `allowed-only`",
+                    package.name
+                ));
+            }
+        }
+        self.error(&errors);
+        Ok(())
+    }
+
+    /// More direct dependencies than `max-dependencies`, listing those with the most transitive
+    /// dependencies first.
+    fn warn_dependency_budget(
+        &mut self,
+        metadata: &Metadata,
+        package: &Package,
+        this_package: &Node,
+        relative_path: &str,
+        config: &ShearConfig,
+    ) -> Result<()> {
+        let Some(max) = config.max_dependencies else { return Ok(()) };
+        if this_package.deps.len() <= max {
+            return Ok(());
+        }
+        let nodes = metadata
+            .resolve
+            .as_ref()
+            .map(|resolve| {
+                resolve.nodes.iter().map(|node| (&node.id, node)).collect::<HashMap<_, _>>()
+            })
+            .unwrap_or_default();
+        let transitive_count = |root: &PackageId| {
+            let mut seen = HashSet::new();
+            let mut stack = vec![root];
+            while let Some(id) = stack.pop() {
+                if let Some(node) = nodes.get(id) {
+                    stack.extend(node.dependencies.iter().filter(|dep| seen.insert(*dep)));
                 }
+            }
+            seen.len()
+        };
+        let mut heaviest = this_package
+            .deps
+            .iter()
+            .map(|node_dep| {
+                Ok((transitive_count(&node_dep.pkg), Self::parse_package_id(&node_dep.pkg.repr)?))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        heaviest.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+        let heaviest = heaviest
+            .iter()
+            .take(5)
+            .map(|(count, name)| format!("`{name}` ({count} transitive)"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        self.warn(&[format!(
+            "dependency budget exceeded: `{}` ({relative_path}) has {} dependencies, more than \
+             `max-dependencies = {max}`; heaviest: {heaviest}",
+            package.name,
+            this_package.deps.len()
+        )]);
+        Ok(())
+    }
+
+    /// Package names of the dependencies `package` declares, whether they are used or not.
+    fn declared_dependencies(metadata: &Metadata, package: &Package) -> Result<Deps> {
+        let node = metadata
+            .resolve
+            .as_ref()
+            .and_then(|resolve| resolve.nodes.iter().find(|node| node.id == package.id))
+            .context("package should exist")?;
+        Ok(Self::dependency_names_map(node, &ShearConfig::default())?.into_values().collect())
+    }
+
+    /// Module name -> package name of the dependencies of `node`.
+    fn dependency_names_map(node: &Node, config: &ShearConfig) -> Result<HashMap<String, String>> {
+        node.deps // `deps` handles renamed dependencies whereas `dependencies` does not
+            .iter()
+            .filter(|node_dep| !config.ignore_sources.contains(source_kind(&node_dep.pkg.repr)))
+            .map(|node_dep| {
+                Self::parse_package_id(&node_dep.pkg.repr)
+                    .map(|package_name| (node_dep.name.clone(), package_name))
             })
             .collect()
     }
 
-    fn process_rust_source(path: &Path) -> Result<Deps> {
-        let source_text = fs::read_to_string(path)?;
-        let imports = collect_imports(&source_text)?;
-        Ok(imports)
+    /// Takes the dependencies in `ignored` out of `dependency_names_map`, returning their module
+    /// name, package name, and the entry naming them.
+    fn take_ignored_dependencies<'a>(
+        package: &Package,
+        config: &ShearConfig<'a>,
+        dependency_names_map: &mut HashMap<String, String>,
+    ) -> Vec<(String, String, &'a str, IgnoredForm)> {
+        let mut ignored = dependency_names_map
+            .iter()
+            .filter_map(|(module_name, package_name)| {
+                let key = Self::dependency_key(package, module_name, package_name);
+                let (entry, form) = config.ignored_entry(key, package_name, module_name)?;
+                Some((module_name.clone(), package_name.clone(), entry, form))
+            })
+            .collect::<Vec<_>>();
+        ignored.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        for (module_name, ..) in &ignored {
+            dependency_names_map.remove(module_name);
+        }
+        ignored
     }
 
-    fn try_fix_package(
+    /// The key in the manifest of the dependency on `package_name` imported as `module_name`,
+    /// which is the name of its `[lib]` unless the dependency is renamed.
+    fn dependency_key<'a>(
+        package: &'a Package,
+        module_name: &str,
+        package_name: &'a str,
+    ) -> &'a str {
+        package
+            .dependencies
+            .iter()
+            .filter(|dep| dep.name == package_name)
+            .find_map(|dep| match &dep.rename {
+                Some(rename) if rename.replace('-', "_") == module_name => Some(rename.as_str()),
+                Some(_) => None,
+                None => Some(dep.name.as_str()),
+            })
+            .unwrap_or(package_name)
+    }
+
+    /// Warns about the entries of the package's own `ignored` naming dependencies which are used,
+    /// with how they name them; entries of the workspace's apply to other members too.
+    fn warn_redundant_ignores(
         &mut self,
-        cargo_toml_path: &Path,
-        unused_dep_names: &[String],
+        package: &Package,
+        relative_path: &str,
+        ignored: &[(String, String, &str, IgnoredForm)],
+        imports: &Deps,
+    ) {
+        let package_ignored = ShearConfig::from_metadata(&package.metadata).ignored;
+        let mut messages = ignored
+            .iter()
+            .filter(|(module_name, _, entry, _)| {
+                imports.contains(module_name) && package_ignored.contains(entry)
+            })
+            .map(|(_, package_name, entry, form)| {
+                format!(
+                    "`{entry}` in `ignored` of `{}` at {relative_path} is redundant: \
+                     `{package_name}`, matched by its {form}, is used",
+                    package.name
+                )
+            })
+            .collect::<Vec<_>>();
+        messages.sort();
+        messages.dedup();
+        self.warn(&messages);
+    }
+
+    /// Takes the dependencies which are not checked by their imports out of `dependency_names_map`:
+    /// dev-dependencies with `skip_dev_dependencies`, `[build-dependencies]` of a package without a
+    /// build script and `[dev-dependencies]` of a package without dev targets, which are reported as
+    /// a whole since none of them can be used.
+    ///
+    /// Returns the package names of the dependencies which are removed, or would be without `--fix`.
+    fn shear_dependency_tables(
+        &mut self,
+        package: &Package,
+        this_package: &Node,
+        relative_path: &str,
+        skip_dev_dependencies: bool,
+        dependency_names_map: &mut HashMap<String, String>,
+    ) -> Result<Vec<String>> {
+        if skip_dev_dependencies {
+            for name in Self::dev_only_dependencies(this_package) {
+                dependency_names_map.remove(name);
+            }
+        }
+        let has_target = |kinds: &[TargetKind]| {
+            package.targets.iter().any(|target| target.kind.iter().any(|kind| kinds.contains(kind)))
+        };
+        let mut removed = vec![];
+        if !has_target(&[TargetKind::CustomBuild]) {
+            removed.extend(self.shear_dependency_table(
+                package,
+                this_package,
+                relative_path,
+                FixKind::UnusedBuildDependencies,
+                dependency_names_map,
+            )?);
+        }
+        // Unit tests and doctests of libraries and binaries use dev-dependencies too.
+        let has_dev_targets =
+            has_target(&[TargetKind::Test, TargetKind::Bench, TargetKind::Example])
+                || package.targets.iter().any(|target| target.test || target.doctest);
+        if !skip_dev_dependencies && !has_dev_targets {
+            removed.extend(self.shear_dependency_table(
+                package,
+                this_package,
+                relative_path,
+                FixKind::UnusedDevDependencies,
+                dependency_names_map,
+            )?);
+        }
+        Ok(removed)
+    }
+
+    /// Reports the dependencies only declared in the table of `kind` as a whole.
+    fn shear_dependency_table(
+        &mut self,
+        package: &Package,
+        this_package: &Node,
+        relative_path: &str,
+        kind: FixKind,
+        dependency_names_map: &mut HashMap<String, String>,
+    ) -> Result<Vec<String>> {
+        let (Some(table_name), Some(dependency_kind)) = (kind.table_name(), kind.dependency_kind())
+        else {
+            return Ok(vec![]);
+        };
+        let mut names = this_package
+            .deps
+            .iter()
+            .filter(|node_dep| node_dep.dep_kinds.iter().all(|info| info.kind == dependency_kind))
+            .filter_map(|node_dep| dependency_names_map.remove(&node_dep.name))
+            .filter(|name| !self.pre_existing.contains(&(Some(package.name.clone()), name.clone())))
+            .collect::<Vec<_>>();
+        if names.is_empty() {
+            return Ok(vec![]);
+        }
+        names.sort();
+
+        let manifest = self.file_provider.read_to_string(package.manifest_path.as_std_path())?;
+        let location = manifest_edit::table_location(&manifest, table_name)
+            .map(|location| format!(":{location}"))
+            .unwrap_or_default();
+        let findings = self.try_fix_package(
+            package.manifest_path.as_std_path(),
+            relative_path,
+            &names,
+            kind,
+            &HashMap::new(),
+        )?;
+        let reason = if dependency_kind == DependencyKind::Build {
+            "`[build-dependencies]` without a build script"
+        } else {
+            "`[dev-dependencies]` but the package has no dev targets"
+        };
+        self.unused_dependencies += names.len();
+        let removed = findings
+            .iter()
+            .filter(|finding| finding.fix.is_none() || finding.is_fixed())
+            .map(|finding| finding.dependency.clone())
+            .collect();
+        self.report_package(package, relative_path, findings, Some((&location, reason)));
+        Ok(removed)
+    }
+
+    /// Module names of the dependencies of `node` which are only `[dev-dependencies]`.
+    fn dev_only_dependencies(node: &Node) -> impl Iterator<Item = &str> {
+        node.deps
+            .iter()
+            .filter(|node_dep| {
+                node_dep.dep_kinds.iter().all(|info| info.kind == DependencyKind::Development)
+            })
+            .map(|node_dep| node_dep.name.as_str())
+    }
+
+    /// Fuzz targets, i.e. packages in a `fuzz` directory, generated by `cargo fuzz` or whose only
+    /// dependency is `libfuzzer-sys`, and packages of criterion benchmarks.
+    fn is_conventional_aux(package: &Package, relative_path: &str) -> bool {
+        let in_dir = |names: &[&str]| {
+            Path::new(relative_path)
+                .parent()
+                .and_then(Path::file_name)
+                .is_some_and(|dir| names.iter().any(|name| dir == *name))
+        };
+        let mut normal_dependencies = package
+            .dependencies
+            .iter()
+            .filter(|dep| dep.kind == DependencyKind::Normal)
+            .map(|dep| dep.name.as_str());
+        let fuzz = in_dir(&["fuzz"])
+            || package.metadata.get("cargo-fuzz").and_then(serde_json::Value::as_bool)
+                == Some(true)
+            || normal_dependencies.clone().eq(["libfuzzer-sys"]);
+        let criterion = in_dir(&["bench", "benches", "benchmarks"])
+            || normal_dependencies.any(|name| name == "criterion");
+        fuzz || criterion
+    }
+
+    /// A dependency in both `[dependencies]` and `[dev-dependencies]` can be declared once, since
+    /// the features of both entries are unified anyway.
+    fn warn_shadowed_dev_dependencies(&mut self, package: &Package, relative_path: &str) {
+        let normal_dependencies = package
+            .dependencies
+            .iter()
+            .filter(|dep| dep.kind == DependencyKind::Normal)
+            .collect::<Vec<_>>();
+        let mut shadowing_warnings = Vec::new();
+        for dev_dep in
+            package.dependencies.iter().filter(|dep| dep.kind == DependencyKind::Development)
+        {
+            let Some(normal_dep) = normal_dependencies.iter().find(|dep| {
+                dep.name == dev_dep.name
+                    && dep.rename == dev_dep.rename
+                    && dep.target == dev_dep.target
+            }) else {
+                continue;
+            };
+            let versions = if normal_dep.req == dev_dep.req {
+                String::new()
+            } else {
+                format!(" with different versions (`{}` and `{}`)", normal_dep.req, dev_dep.req)
+            };
+            shadowing_warnings.push(format!(
+                "`{}` of `{}` ({relative_path}) is in both `[dependencies]` and `[dev-dependencies]`\
+                 {versions}; consider declaring it once in `[dependencies]` with the union of the features",
+                dev_dep.name, package.name
+            ));
+        }
+        self.warn(&shadowing_warnings);
+    }
+
+    fn warn_feature_graph(
+        &mut self,
+        package: &Package,
+        relative_path: &str,
+        unused_module_names: &[String],
+    ) -> Result<()> {
+        let graph = FeatureGraph::new(&package.features);
+        let unused_module_names = unused_module_names.iter().cloned().collect::<HashSet<_>>();
+        let mut messages = graph
+            .messages(&unused_module_names)
+            .into_iter()
+            .map(|message| format!("`{}` ({relative_path}): {message}", package.name))
+            .collect::<Vec<_>>();
+
+        let unused_optional = package
+            .dependencies
+            .iter()
+            .filter(|dep| dep.optional)
+            .map(|dep| dep.rename.as_ref().unwrap_or(&dep.name).replace('-', "_"))
+            .filter(|module_name| unused_module_names.contains(module_name))
+            .collect::<HashSet<_>>();
+        let bloated = graph.bloated_default(&unused_optional);
+        if !bloated.is_empty() {
+            let manifest =
+                self.file_provider.read_to_string(package.manifest_path.as_std_path())?;
+            for (entry, dependency) in bloated {
+                let location = feature_graph::default_entry_location(&manifest, entry).map_or_else(
+                    || relative_path.to_string(),
+                    |(line, column)| format!("{relative_path}:{line}:{column}"),
+                );
+                messages.push(format!(
+                    "the `default` feature of `{}` enables the unused optional dependency \
+                     `{dependency}` through `{entry}` at {location}, which every consumer pulls in",
+                    package.name
+                ));
+            }
+        }
+
+        self.warn(&messages);
+        Ok(())
+    }
+
+    /// Package names of the unused dependencies to report, without those already unused at
+    /// `--compare-ref` and the `link-only` ones, which are expected not to be used in the source
+    /// and are warned about with their reason instead.
+    fn unused_dependency_names(
+        &mut self,
+        package: &Package,
+        relative_path: &str,
+        config: &ShearConfig,
+        dependency_names_map: &HashMap<String, String>,
+        unused_module_names: Vec<String>,
+    ) -> Result<Vec<String>> {
+        let (link_only, unused_dependency_names): (Vec<_>, Vec<_>) = unused_module_names
+            .into_iter()
+            .map(|name| dependency_names_map[&name].clone())
+            .filter(|name| !self.pre_existing.contains(&(Some(package.name.clone()), name.clone())))
+            .partition(|name| config.link_only.contains_key(name.as_str()));
+        if link_only.is_empty() {
+            return Ok(unused_dependency_names);
+        }
+        let manifest = self.file_provider.read_to_string(package.manifest_path.as_std_path())?;
+        let messages = link_only
+            .into_iter()
+            .map(|name| {
+                let reason = config.link_only[name.as_str()];
+                let location = manifest_edit::dependency_location(&manifest, &name)
+                    .map_or_else(String::new, |location| format!(":{location}"));
+                format!(
+                    "`{name}` of `{}` at {relative_path}{location} is not used in the source \
+                     and kept as link-only: {reason}",
+                    package.name
+                )
+            })
+            .collect::<Vec<_>>();
+        self.warn(&messages);
+        Ok(unused_dependency_names)
+    }
+
+    /// Required dependencies which are only used by code for documentation builds, see
+    /// [`Imports::doc_only_deps`].
+    fn warn_doc_only_dependencies(
+        &mut self,
+        package: &Package,
+        relative_path: &str,
+        dependency_names_map: &HashMap<String, String>,
+        doc_only_deps: &Deps,
+    ) -> Result<()> {
+        let mut names = doc_only_deps
+            .iter()
+            .filter_map(|module_name| dependency_names_map.get(module_name))
+            .filter(|name| {
+                package.dependencies.iter().any(|dep| {
+                    dep.name == **name && dep.kind == DependencyKind::Normal && !dep.optional
+                })
+            })
+            .collect::<Vec<_>>();
+        if names.is_empty() {
+            return Ok(());
+        }
+        names.sort();
+        let manifest = self.file_provider.read_to_string(package.manifest_path.as_std_path())?;
+        let messages = names
+            .into_iter()
+            .map(|name| {
+                let location = manifest_edit::dependency_location(&manifest, name)
+                    .map_or_else(String::new, |location| format!(":{location}"));
+                format!(
+                    "`{name}` of `{}` at {relative_path}{location} is only used under \
+                     `#[cfg(doc)]` or `#[cfg(docsrs)]`; consider making it optional",
+                    package.name
+                )
+            })
+            .collect::<Vec<_>>();
+        self.warn(&messages);
+        Ok(())
+    }
+
+    /// `features = ["derive"]` added to a `workspace = true` dependency whose derive macros are not used.
+    fn warn_unused_derive_features(
+        &mut self,
+        package: &Package,
+        relative_path: &str,
+        derive_crates: &Deps,
     ) -> Result<()> {
-        if !self.options.fix {
+        let manifest = self.file_provider.read_to_string(package.manifest_path.as_std_path())?;
+        let messages = feature_graph::inherited_derive_features(&manifest)
+            .into_iter()
+            .filter(|(key, _, _)| !derive_crates.contains(&key.replace('-', "_")))
+            .map(|(key, line, column)| {
+                format!(
+                    "`{key}` of `{}` enables the `derive` feature at {relative_path}:{line}:{column}, \
+                     but no `#[derive]` from `{key}` is used",
+                    package.name
+                )
+            })
+            .collect::<Vec<_>>();
+        self.warn(&messages);
+        Ok(())
+    }
+
+    /// With `--unused-reexports`, warns about `pub use` items in the `lib.rs` of `packages` which
+    /// re-export from a dependency but are not used by the package's other targets or by the
+    /// workspace members depending on it.
+    fn warn_unused_reexports(&mut self, metadata: &Metadata, packages: &[&Package]) -> Result<()> {
+        if !self.options.unused_reexports {
             return Ok(());
         }
+        let nodes = &metadata
+            .resolve
+            .as_ref()
+            .context("`cargo_metadata::MetadataCommand::no_deps` should not be called.")?
+            .nodes;
+        for package in packages {
+            let Some(lib) = package.targets.iter().find(|target| {
+                target.kind.iter().any(|kind| matches!(kind, TargetKind::Lib | TargetKind::RLib))
+            }) else {
+                continue;
+            };
+            let Some(node) = nodes.iter().find(|node| node.id == package.id) else { continue };
+            let lib_path = lib.src_path.as_std_path();
+            let pub_uses = process_rust_source(
+                self.file_provider.as_ref(),
+                lib_path,
+                self.options.max_parse_size,
+                &[],
+            )?
+            .pub_uses
+            .into_iter()
+            .filter(|(_, krate)| node.deps.iter().any(|node_dep| node_dep.name == *krate))
+            .collect::<Vec<_>>();
+            if pub_uses.is_empty() {
+                continue;
+            }
+            // The package's other targets, and the members depending on it under their own names.
+            let mut users = vec![(package.id.clone(), lib.name.replace('-', "_"))];
+            users.extend(
+                nodes.iter().filter(|node| metadata.workspace_members.contains(&node.id)).flat_map(
+                    |node| {
+                        node.deps
+                            .iter()
+                            .filter(|node_dep| node_dep.pkg == package.id)
+                            .map(|node_dep| (node.id.clone(), node_dep.name.clone()))
+                    },
+                ),
+            );
+            let mut used = Deps::new();
+            for (id, name) in &users {
+                let item_paths = &self.member_usage(metadata, id)?.item_paths;
+                if item_paths.contains(&format!("{name}::*")) {
+                    used.extend(pub_uses.iter().map(|(item, _)| item.clone()));
+                }
+                used.extend(
+                    pub_uses
+                        .iter()
+                        .filter(|(item, _)| item_paths.contains(&format!("{name}::{item}")))
+                        .map(|(item, _)| item.clone()),
+                );
+            }
+            let relative_path = lib_path
+                .strip_prefix(metadata.workspace_root.as_std_path())
+                .unwrap_or(lib_path)
+                .to_string_lossy();
+            let messages = pub_uses
+                .iter()
+                .filter(|(item, _)| !used.contains(item))
+                .map(|(item, krate)| {
+                    format!(
+                        "`{item}` re-exported from `{krate}` in `{}` ({relative_path}) \
+                         is not used by any workspace member",
+                        package.name
+                    )
+                })
+                .collect::<Vec<_>>();
+            self.warn(&messages);
+        }
+        Ok(())
+    }
+
+    /// Warns about dependency tables without any dependency, which `--fix` removes when it empties
+    /// them but leaves alone otherwise, path dependencies of a publishable `package` without a
+    /// version, and with `--check-versions` about version requirements which accept any future
+    /// version. Checks the manifest of the workspace root without a `package`.
+    fn lint_manifest(
+        &mut self,
+        metadata: &Metadata,
+        package: Option<&Package>,
+        relative_path: &str,
+    ) -> Result<()> {
+        let manifest_path = package.map_or_else(
+            || metadata.workspace_root.join("Cargo.toml"),
+            |package| package.manifest_path.clone(),
+        );
+        let manifest = self.file_provider.read_to_string(manifest_path.as_std_path())?;
+        let mut messages = manifest_edit::empty_dependency_tables(&manifest)
+            .into_iter()
+            .map(|(header, location)| {
+                format!("`[{header}]` at {relative_path}:{location} is empty and can be removed")
+            })
+            .collect::<Vec<_>>();
+        // `publish = false` is an empty list of registries.
+        if let Some(package) = package.filter(|package| {
+            package.publish.as_ref().is_none_or(|registries| !registries.is_empty())
+        }) {
+            // `cargo publish` strips dev-dependencies without a version.
+            let path_only = package.dependencies.iter().filter(|dep| {
+                dep.path.is_some()
+                    && dep.req == semver::VersionReq::STAR
+                    && dep.kind != DependencyKind::Development
+            });
+            for dep in path_only {
+                let location = manifest_edit::dependency_location(&manifest, &dep.name)
+                    .map_or_else(String::new, |location| format!(":{location}"));
+                messages.push(format!(
+                    "`{}` depends on `{}` at {relative_path}{location} by `path` without a `version`, \
+                     which `cargo publish` rejects; add a `version` or `publish = false`",
+                    package.name, dep.name
+                ));
+            }
+        }
+        if self.options.check_versions {
+            for (name, req, location) in manifest_edit::version_requirements(&manifest) {
+                let Some(problem) = Self::broad_version_requirement(&req) else { continue };
+                // The newest locked version the requirement matches, as a concrete suggestion.
+                let locked = semver::VersionReq::parse(&req).ok().and_then(|parsed| {
+                    metadata
+                        .packages
+                        .iter()
+                        .filter(|package| package.name == name && package.source.is_some())
+                        .map(|package| &package.version)
+                        .filter(|version| parsed.matches(version))
+                        .max()
+                });
+                let suggestion = locked.map_or_else(String::new, |version| {
+                    format!(", e.g. `\"{version}\"` from Cargo.lock")
+                });
+                messages.push(format!(
+                    "`{name}` requires `{req}` at {relative_path}:{location}, which {problem}; \
+                     use a bounded requirement{suggestion}"
+                ));
+            }
+        }
+        self.warn(&messages);
+        Ok(())
+    }
 
-        let manifest = fs::read_to_string(cargo_toml_path)?;
-        let mut manifest = toml_edit::DocumentMut::from_str(&manifest)?;
+    /// Why `req` is too broad: it is `*`, or has no upper bound like `>=1.0`.
+    fn broad_version_requirement(req: &str) -> Option<&'static str> {
+        let parsed = semver::VersionReq::parse(req).ok()?;
+        if parsed.comparators.is_empty() {
+            return Some("accepts any version");
+        }
+        let unbounded = parsed
+            .comparators
+            .iter()
+            .all(|comparator| matches!(comparator.op, semver::Op::Greater | semver::Op::GreaterEq));
+        unbounded.then_some("has no upper bound")
+    }
 
-        // Try `[workspace.dependencies]`
-        if let Some(dependencies) = manifest
-            .get_mut("workspace")
-            .and_then(|item| item.as_table_mut())
-            .and_then(|table| table.get_mut("dependencies"))
-            .and_then(|item| item.as_table_mut())
-        {
-            for k in unused_dep_names {
-                dependencies.remove(k);
+    /// Prints policy violations, which fail the check like unused dependencies.
+    fn error(&mut self, messages: &[String]) {
+        if messages.is_empty() {
+            return;
+        }
+        self.errors.extend_from_slice(messages);
+        if self.quiet {
+            return;
+        }
+        if self.options.format == Format::JsonLines {
+            for message in messages {
+                println!("{}", JsonLine::Error { message });
+            }
+        } else if self.options.format == Format::Human {
+            for message in messages {
+                println!("error: {message}");
+            }
+            println!();
+        }
+    }
+
+    /// Prints warnings unless they are hidden by `--min-severity`.
+    fn warn(&mut self, messages: &[String]) {
+        if messages.is_empty() {
+            return;
+        }
+        if self.options.min_severity > Severity::Warn {
+            self.suppressed_warnings += messages.len();
+            return;
+        }
+        self.warnings.extend_from_slice(messages);
+        if self.quiet {
+            return;
+        }
+        if self.options.format == Format::JsonLines {
+            for message in messages {
+                println!("{}", JsonLine::Warning { message });
+            }
+        } else if self.options.format == Format::Human {
+            for message in messages {
+                println!("warning: {message}");
             }
+            println!();
+        }
+    }
+
+    fn parse_package_specs(specs: &[String]) -> Result<Vec<PackageSpec>> {
+        specs.iter().map(|spec| PackageSpec::parse(spec)).collect()
+    }
+
+    fn parse_package_id(s: &str) -> Result<String> {
+        // The node id can have multiple representations:
+        if s.contains(' ') {
+            // < Rust 1.77 : `memchr 2.7.1 (registry+https://github.com/rust-lang/crates.io-index)`
+            s.split(' ')
+                .next()
+                .map(ToString::to_string)
+                .ok_or_else(|| anyhow::anyhow!("{s} should have a space"))
+        } else {
+            // >= Rust 1.77: `registry+https://github.com/rust-lang/crates.io-index#memchr@2.7.1`
+            PackageIdSpec::parse(s)
+                .map(|id| id.name().to_string())
+                .map_err(|err| anyhow::anyhow!(err))
         }
+    }
+
+    /// Entry points of the targets of `package`, from which its source files are reached.
+    fn get_package_roots(package: &Package) -> Vec<PathBuf> {
+        let roots = package
+            .targets
+            .iter()
+            .map(|target| target.src_path.clone().into_std_path_buf())
+            .collect::<BTreeSet<_>>();
+        roots.into_iter().collect()
+    }
 
-        // Try `[dependencies]`, `[dev-dependencies]`, `[build-dependencies]`
-        for table_name in ["dependencies", "dev-dependencies", "build-dependencies"] {
-            if let Some(dependencies) =
-                manifest.get_mut(table_name).and_then(|item| item.as_table_mut())
+    /// Guesses why each unused dependency of `package` is declared, see [`Category`].
+    fn categorize_findings(
+        metadata: &Metadata,
+        package: &Package,
+        node: &Node,
+        findings: &mut [Finding],
+    ) {
+        for finding in findings {
+            let declarations = package
+                .dependencies
+                .iter()
+                .filter(|dep| dep.name == finding.dependency)
+                .collect::<Vec<_>>();
+            let is_proc_macro = node
+                .deps
+                .iter()
+                .map(|node_dep| &metadata[&node_dep.pkg])
+                .filter(|dependency| dependency.name == finding.dependency)
+                .flat_map(|dependency| &dependency.targets)
+                .any(|target| target.kind.contains(&TargetKind::ProcMacro));
+            finding.category = if is_proc_macro {
+                Category::MacroOnly
+            } else if declarations.iter().any(|dep| !dep.features.is_empty()) {
+                Category::FeatureOnly
+            } else if !declarations.is_empty()
+                && declarations.iter().all(|dep| dep.kind == DependencyKind::Development)
             {
-                for k in unused_dep_names {
-                    dependencies.remove(k);
-                }
+                Category::DevOnly
+            } else {
+                Category::Unused
+            };
+        }
+    }
+
+    /// Unused dependencies (package name -> reason) whose removal would disable features which
+    /// other workspace members currently get through feature unification.
+    fn feature_unification_risks(
+        metadata: &Metadata,
+        package: &Package,
+        unused_dep_names: &[String],
+    ) -> HashMap<String, String> {
+        let enabled_features = |dep: &cargo_metadata::Dependency| {
+            let default = dep.uses_default_features.then(|| "default".to_string());
+            dep.features.iter().cloned().chain(default).collect::<BTreeSet<_>>()
+        };
+        let mut risks = HashMap::new();
+        for name in unused_dep_names {
+            let others = metadata
+                .workspace_packages()
+                .into_iter()
+                .filter(|member| member.id != package.id)
+                .flat_map(|member| &member.dependencies)
+                .filter(|dep| &dep.name == name)
+                .collect::<Vec<_>>();
+            if others.is_empty() {
+                continue;
+            }
+            let kept_features =
+                others.into_iter().flat_map(enabled_features).collect::<BTreeSet<_>>();
+            let lost_features = package
+                .dependencies
+                .iter()
+                .filter(|dep| &dep.name == name)
+                .flat_map(enabled_features)
+                .filter(|feature| !kept_features.contains(feature))
+                .collect::<BTreeSet<_>>();
+            if !lost_features.is_empty() {
+                let lost_features = lost_features.into_iter().collect::<Vec<_>>().join("`, `");
+                risks.insert(
+                    name.clone(),
+                    format!("other members may rely on its features `{lost_features}`"),
+                );
+            }
+        }
+        risks
+    }
+
+    /// Removes `unused_dep_names` (package names) from the manifest and records the outcome of each.
+    ///
+    /// `relative_path` is the manifest path relative to the workspace root, used in `--emit-patch`.
+    fn try_fix_package(
+        &mut self,
+        cargo_toml_path: &Path,
+        relative_path: &str,
+        unused_dep_names: &[String],
+        kind: FixKind,
+        kept: &HashMap<String, String>,
+    ) -> Result<Vec<Finding>> {
+        let original = self.file_provider.read_to_string(cargo_toml_path)?;
+        let parsed = toml_edit::DocumentMut::from_str(&original)?;
+        let mut findings = unused_dep_names
+            .iter()
+            .map(|name| Finding {
+                code: kind.code(),
+                location: manifest_edit::dependency_location(&original, name),
+                edits: self.planned_edits(relative_path, &original, &parsed, name, kind, kept),
+                ..Finding::new(name.clone())
+            })
+            .collect::<Vec<_>>();
+        if !self.options.fix && !self.options.fix_suppress && self.options.emit_patch.is_none() {
+            return Ok(findings);
+        }
+        if (!self.options.fix_only.is_empty() && !self.options.fix_only.contains(&kind))
+            || self.options.fix_except.contains(&kind)
+        {
+            for finding in &mut findings {
+                finding.fix = Some(FixOutcome::Skipped(format!("`{}` not selected", kind.code())));
+            }
+            return Ok(findings);
+        }
+
+        let mut manifest = parsed;
+        if self.options.fix_suppress {
+            Self::suppress_findings(&mut manifest, &mut findings, kind);
+        } else {
+            let mut plan = EditPlan::default();
+            for finding in &mut findings {
+                let outcome = Self::plan_fix(&mut plan, &manifest, &finding.dependency, kind, kept);
+                finding.fix = Some(outcome);
             }
+            plan.apply(&mut manifest, self.edit_style());
         }
 
-        self.fixed_dependencies += unused_dep_names.len();
         let serialized = manifest.to_string();
-        fs::write(cargo_toml_path, serialized)?;
-        Ok(())
+        if self.options.emit_patch.is_some() {
+            let diff = TextDiff::from_lines(&original, &serialized)
+                .unified_diff()
+                .header(&format!("a/{relative_path}"), &format!("b/{relative_path}"))
+                .to_string();
+            self.patch.push_str(&diff);
+            for finding in &mut findings {
+                if finding.is_resolved() {
+                    finding.fix = None;
+                }
+            }
+            return Ok(findings);
+        }
+
+        if serialized == original {
+            return Ok(findings);
+        }
+        let current = self.file_provider.read_to_string(cargo_toml_path)?;
+        if self
+            .manifest_hashes
+            .get(cargo_toml_path)
+            .is_some_and(|hash| *hash != content_hash(&current))
+        {
+            for finding in &mut findings {
+                if finding.is_resolved() {
+                    finding.fix = Some(FixOutcome::Failed(
+                        "manifest changed on disk since it was analyzed".to_string(),
+                    ));
+                }
+            }
+            return Ok(findings);
+        }
+        let backed_up = if self.options.backup || self.options.backup_dir.is_some() {
+            let backup_path = backup::backup_path(
+                cargo_toml_path,
+                relative_path,
+                self.options.backup_dir.as_deref(),
+            );
+            backup::back_up(cargo_toml_path, &backup_path)
+        } else {
+            Ok(())
+        };
+        if let Err(err) = backed_up.and_then(|()| fs::write(cargo_toml_path, &serialized)) {
+            for finding in &mut findings {
+                if finding.is_resolved() {
+                    finding.fix = Some(FixOutcome::Failed(err.to_string()));
+                }
+            }
+            return Ok(findings);
+        }
+        // Later fixes of the same manifest, e.g. of `[workspace.dependencies]` in a root package,
+        // start from this version.
+        self.manifest_hashes.insert(cargo_toml_path.to_path_buf(), content_hash(&serialized));
+        self.fixed_dependencies += findings.iter().filter(|finding| finding.is_resolved()).count();
+        if !self.fixed_manifests.iter().any(|path| path == cargo_toml_path) {
+            self.fixed_manifests.push(cargo_toml_path.to_path_buf());
+        }
+        Ok(findings)
+    }
+
+    /// The edits fixing `dependency` alone would make to the manifest at `relative_path`.
+    fn planned_edits(
+        &self,
+        relative_path: &str,
+        original: &str,
+        parsed: &toml_edit::DocumentMut,
+        dependency: &str,
+        kind: FixKind,
+        kept: &HashMap<String, String>,
+    ) -> Vec<TextEdit> {
+        let mut plan = EditPlan::default();
+        if Self::plan_fix(&mut plan, parsed, dependency, kind, kept) != FixOutcome::Fixed {
+            return vec![];
+        }
+        let mut planned = parsed.clone();
+        plan.apply(&mut planned, self.edit_style());
+        TextEdit::diff(relative_path, original, &planned.to_string())
+    }
+
+    /// With `--fix-suppress`, adds the dependencies of `findings` to `ignored` in the package's or,
+    /// for `[workspace.dependencies]`, the workspace's `cargo-shear` metadata.
+    fn suppress_findings(
+        manifest: &mut toml_edit::DocumentMut,
+        findings: &mut [Finding],
+        kind: FixKind,
+    ) {
+        let section =
+            if kind == FixKind::UnusedWorkspaceDependency { "workspace" } else { "package" };
+        let names = findings.iter().map(|finding| finding.dependency.as_str()).collect::<Vec<_>>();
+        let outcome = manifest_edit::add_ignored(manifest, section, &names)
+            .map_or_else(FixOutcome::Skipped, |()| FixOutcome::Suppressed);
+        for finding in findings {
+            finding.fix = Some(outcome.clone());
+        }
+    }
+
+    /// Plans the fix of `kind` for `dependency`, unless it is `kept` for the given reason.
+    fn plan_fix(
+        plan: &mut EditPlan,
+        manifest: &toml_edit::DocumentMut,
+        dependency: &str,
+        kind: FixKind,
+        kept: &HashMap<String, String>,
+    ) -> FixOutcome {
+        // Every dependency of the table is unused, so the table goes as a whole.
+        let op = if let Some(table_name) = kind.table_name() {
+            EditOp::RemoveTable(table_name)
+        } else if let Some(reason) = kept.get(dependency) {
+            return FixOutcome::Skipped(reason.clone());
+        } else {
+            EditOp::RemoveDependency(dependency.to_string())
+        };
+        plan.plan(manifest, op).map_or_else(FixOutcome::Skipped, |()| FixOutcome::Fixed)
+    }
+
+    const fn edit_style(&self) -> EditStyle {
+        EditStyle { sort_deps: self.options.sort_deps, tidy_comments: self.options.tidy_comments }
     }
 }
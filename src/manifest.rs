@@ -0,0 +1,121 @@
+use std::ops::Range;
+
+use anyhow::Result;
+use toml_edit::{DocumentMut, ImDocument, Item, Key, TableLike};
+
+/// Parses a manifest, ignoring the fields which `cargo_toml` cannot parse, e.g. syntax behind
+/// `cargo-features` or newer than it, instead of failing on them.
+///
+/// Returns the manifest and a warning for each ignored field. Sections it does not know about
+/// are ignored anyway; only invalid TOML is an error.
+pub fn parse(source: &str) -> Result<(cargo_toml::Manifest, Vec<String>)> {
+    let mut source = source.to_string();
+    let mut warnings = vec![];
+    loop {
+        let err = match cargo_toml::Manifest::from_str(&source) {
+            Ok(manifest) => return Ok((manifest, warnings)),
+            Err(err) => err,
+        };
+        let Some((path, message)) = invalid_field(&source) else { return Err(err.into()) };
+        let mut document = source.parse::<DocumentMut>()?;
+        let Some((last, parents)) = path.split_last() else { return Err(err.into()) };
+        let mut table: &mut dyn TableLike = document.as_table_mut();
+        for key in parents {
+            let Some(child) = table.get_mut(key).and_then(Item::as_table_like_mut) else {
+                return Err(err.into());
+            };
+            table = child;
+        }
+        table.remove(last);
+        warnings.push(format!(
+            "ignoring `{}`, which could not be parsed: {message}",
+            path.iter()
+                .map(|key| Key::new(key.as_str()).display_repr().into_owned())
+                .collect::<Vec<_>>()
+                .join(".")
+        ));
+        source = document.to_string();
+    }
+}
+
+/// Key path of the innermost field the manifest fails to deserialize at, and why.
+fn invalid_field(source: &str) -> Option<(Vec<String>, String)> {
+    let err = toml_edit::de::from_str::<cargo_toml::Manifest>(source).err()?;
+    let offset = err.span()?.start;
+    let document = ImDocument::parse(source).ok()?;
+    let mut path = vec![];
+    let mut table: &dyn TableLike = document.as_table();
+    'descend: loop {
+        for (name, _) in table.iter() {
+            let Some((key, item)) = table.get_key_value(name) else { continue };
+            if !extent(key, item).is_some_and(|extent| extent.contains(&offset)) {
+                continue;
+            }
+            path.push(name.to_string());
+            match item.as_table_like() {
+                Some(child) => {
+                    table = child;
+                    continue 'descend;
+                }
+                None => break 'descend,
+            }
+        }
+        break;
+    }
+    (!path.is_empty()).then(|| (path, err.message().to_string()))
+}
+
+/// Span of a field in the manifest, from its key to the end of its last nested field; the span of
+/// a `[table]` only covers its header.
+fn extent(key: &Key, item: &Item) -> Option<Range<usize>> {
+    let tables = match item {
+        Item::ArrayOfTables(array) => array.iter().map(|table| table as &dyn TableLike).collect(),
+        _ => item.as_table_like().into_iter().collect::<Vec<_>>(),
+    };
+    let nested = tables.into_iter().flat_map(|table| {
+        table
+            .iter()
+            .filter_map(|(name, _)| table.get_key_value(name))
+            .filter_map(|(key, item)| extent(key, item))
+            .collect::<Vec<_>>()
+    });
+    key.span()
+        .into_iter()
+        .chain(item.span())
+        .chain(nested)
+        .reduce(|a, b| a.start.min(b.start)..a.end.max(b.end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse;
+
+    #[test]
+    fn ignores_invalid_fields() {
+        let source = r#"
+cargo-features = ["edition2077"]
+
+[package]
+name = "a"
+edition = "2077"
+
+[dependencies]
+itoa = { version = 1 }
+ryu = "1"
+
+[target.'cfg(unix)'.dependencies]
+libc = { version = "0.2", optional = "yes" }
+"#;
+        let (manifest, warnings) = parse(source).unwrap();
+        assert_eq!(manifest.package().name, "a");
+        assert_eq!(manifest.dependencies.keys().collect::<Vec<_>>(), ["ryu"]);
+        let paths =
+            warnings.iter().map(|warning| warning.split('`').nth(1).unwrap()).collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            ["package.edition", "dependencies.itoa", "target.\"cfg(unix)\".dependencies.libc"]
+        );
+
+        assert!(parse("[package").is_err());
+    }
+}
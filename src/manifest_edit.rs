@@ -0,0 +1,563 @@
+use toml_edit::{Array, DocumentMut, ImDocument, Item, Table, TableLike};
+
+use crate::output::Location;
+
+const DEPENDENCY_TABLES: [&str; 3] = ["dependencies", "dev-dependencies", "build-dependencies"];
+
+/// `[workspace.dependencies]`, `[dependencies]`, `[dev-dependencies]` and `[build-dependencies]`,
+/// and their `[target.'cfg(..)'.dependencies]` variants.
+pub fn dependency_tables(manifest: &mut DocumentMut) -> Vec<&mut Table> {
+    let mut tables = vec![];
+    for (key, item) in manifest.iter_mut() {
+        match key.get() {
+            "workspace" => {
+                tables.extend(item.get_mut("dependencies").and_then(|item| item.as_table_mut()));
+            }
+            "target" => {
+                let targets =
+                    item.as_table_mut().into_iter().flat_map(|targets| targets.iter_mut());
+                for (_, target) in targets {
+                    let Some(target) = target.as_table_mut() else { continue };
+                    tables.extend(target.iter_mut().filter_map(|(key, item)| {
+                        DEPENDENCY_TABLES
+                            .contains(&key.get())
+                            .then(|| item.as_table_mut())
+                            .flatten()
+                    }));
+                }
+            }
+            key if DEPENDENCY_TABLES.contains(&key) => tables.extend(item.as_table_mut()),
+            _ => {}
+        }
+    }
+    tables
+}
+
+/// Read-only [`dependency_tables`], with their key paths, e.g. `["target", "cfg(unix)", "dependencies"]`.
+fn dependency_tables_ref(manifest: &Table) -> Vec<(Vec<&str>, &Table)> {
+    let mut tables = vec![];
+    for (key, item) in manifest {
+        match key {
+            "workspace" => tables.extend(
+                item.get("dependencies")
+                    .and_then(|item| item.as_table())
+                    .map(|table| (vec![key, "dependencies"], table)),
+            ),
+            "target" => {
+                let targets = item.as_table().into_iter().flat_map(|targets| targets.iter());
+                for (target_key, target) in targets {
+                    let Some(target) = target.as_table() else { continue };
+                    tables.extend(target.iter().filter_map(|(name, item)| {
+                        let table =
+                            item.as_table().filter(|_| DEPENDENCY_TABLES.contains(&name))?;
+                        Some((vec![key, target_key, name], table))
+                    }));
+                }
+            }
+            key if DEPENDENCY_TABLES.contains(&key) => {
+                tables.extend(item.as_table().map(|table| (vec![key], table)));
+            }
+            _ => {}
+        }
+    }
+    tables
+}
+
+/// Key paths of the empty dependency tables of `manifest`.
+fn empty_dependency_table_paths(manifest: &Table) -> Vec<Vec<String>> {
+    dependency_tables_ref(manifest)
+        .into_iter()
+        .filter(|(_, table)| table.is_empty())
+        .map(|(path, _)| path.into_iter().map(str::to_string).collect())
+        .collect()
+}
+
+/// The headers of the empty dependency tables of `manifest`, e.g. `target.'cfg(unix)'.dependencies`,
+/// with their location.
+pub fn empty_dependency_tables(manifest: &str) -> Vec<(String, Location)> {
+    let Ok(document) = ImDocument::parse(manifest) else { return vec![] };
+    dependency_tables_ref(&document)
+        .into_iter()
+        .filter(|(_, table)| table.is_empty() && !table.is_implicit())
+        .filter_map(|(path, table)| {
+            let header = path
+                .iter()
+                .map(|key| {
+                    if key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+                        (*key).to_string()
+                    } else {
+                        format!("'{key}'")
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(".");
+            Some((header, Location::new(manifest, table.span()?.start)))
+        })
+        .collect()
+}
+
+/// The version requirements of the dependencies in `manifest`, by package name, with their
+/// location. Dependencies inherited with `workspace = true` have theirs in the workspace manifest.
+pub fn version_requirements(manifest: &str) -> Vec<(String, String, Location)> {
+    let Ok(document) = ImDocument::parse(manifest) else { return vec![] };
+    let mut requirements = vec![];
+    for (_, table) in dependency_tables_ref(&document) {
+        for (key, item) in table {
+            let (name, version) = match item.as_table_like() {
+                Some(dependency) => {
+                    if dependency.get("workspace").and_then(toml_edit::Item::as_bool) == Some(true)
+                    {
+                        continue;
+                    }
+                    let name = dependency.get("package").and_then(|item| item.as_str());
+                    let Some(version) = dependency.get("version") else { continue };
+                    (name.unwrap_or(key), version)
+                }
+                None => (key, item),
+            };
+            let (Some(req), Some(span)) = (version.as_str(), version.span()) else { continue };
+            requirements.push((
+                name.to_string(),
+                req.to_string(),
+                Location::new(manifest, span.start),
+            ));
+        }
+    }
+    requirements
+}
+
+/// Removes the table at the key path `path`, and `[target.'cfg(..)']` if it is left empty.
+fn remove_table(manifest: &mut DocumentMut, path: &[String]) {
+    let Some((last, parents)) = path.split_last() else { return };
+    let mut table = manifest.as_table_mut();
+    for key in parents {
+        let Some(parent) = table.get_mut(key).and_then(|item| item.as_table_mut()) else { return };
+        table = parent;
+    }
+    table.remove(last);
+    if let [target, _, _] = path {
+        if let Some(targets) = manifest.get_mut(target).and_then(|item| item.as_table_mut()) {
+            targets.retain(|_, target| target.as_table().is_none_or(|target| !target.is_empty()));
+        }
+    }
+}
+
+/// A change to a manifest, planned against the parsed manifest and applied later.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EditOp {
+    /// Removes the dependency on a package from every dependency table, except optional ones.
+    RemoveDependency(String),
+
+    /// Removes `[name]` and its target specific variants.
+    RemoveTable(&'static str),
+}
+
+/// Formatting of the dependency tables an [`EditOp::RemoveDependency`] removes from.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EditStyle {
+    pub sort_deps: bool,
+
+    pub tidy_comments: bool,
+}
+
+/// The operations to apply to one manifest.
+#[derive(Debug, Clone, Default)]
+pub struct EditPlan {
+    ops: Vec<EditOp>,
+}
+
+impl EditPlan {
+    /// Adds `op` if it can be applied to `manifest`, otherwise returns why it is skipped.
+    pub fn plan(&mut self, manifest: &DocumentMut, op: EditOp) -> Result<(), String> {
+        match &op {
+            EditOp::RemoveDependency(name) => {
+                let mut optional = false;
+                let removable = dependency_tables_ref(manifest).into_iter().any(|(_, table)| {
+                    find_dependency_key(table, name).is_some_and(|key| {
+                        // Removing an optional dependency would break the features enabling it.
+                        optional |= is_optional(table, &key);
+                        !is_optional(table, &key)
+                    })
+                });
+                if !removable {
+                    return Err(if optional {
+                        "optional".to_string()
+                    } else {
+                        "not in a `[dependencies]` table".to_string()
+                    });
+                }
+            }
+            EditOp::RemoveTable(name) => {
+                if has_optional_dependency(manifest, name) {
+                    return Err("optional".to_string());
+                }
+                if tables_named(manifest, name).next().is_none() {
+                    return Err(format!("no `[{name}]` table"));
+                }
+            }
+        }
+        if !self.ops.contains(&op) {
+            self.ops.push(op);
+        }
+        Ok(())
+    }
+
+    /// Applies the planned operations, also removing the dependency tables they leave empty.
+    pub fn apply(&self, manifest: &mut DocumentMut, style: EditStyle) {
+        let empty_before = empty_dependency_table_paths(manifest);
+        for op in &self.ops {
+            match op {
+                EditOp::RemoveDependency(name) => {
+                    for table in dependency_tables(manifest) {
+                        let Some(key) = find_dependency_key(table, name) else { continue };
+                        if is_optional(table, &key) {
+                            continue;
+                        }
+                        table.remove(&key);
+                        if style.sort_deps {
+                            table.sort_values();
+                        }
+                        if style.tidy_comments {
+                            tidy_blank_lines(table);
+                        }
+                    }
+                }
+                EditOp::RemoveTable(name) => {
+                    remove_dependency_tables(manifest, name);
+                }
+            }
+        }
+        for path in empty_dependency_table_paths(manifest) {
+            if !empty_before.contains(&path) {
+                remove_table(manifest, &path);
+            }
+        }
+    }
+}
+
+/// The key of `package_name` in a dependency table, e.g. `json` for `json = { package = "serde_json" }`.
+pub fn find_dependency_key(table: &Table, package_name: &str) -> Option<String> {
+    table
+        .iter()
+        .find(|(key, item)| {
+            item.get("package")
+                .and_then(|item| item.as_str())
+                .map_or(*key == package_name, |name| name == package_name)
+        })
+        .map(|(key, _)| key.to_string())
+}
+
+/// Whether the dependency at `key` is `optional = true`.
+pub fn is_optional(table: &Table, key: &str) -> bool {
+    table.get(key).and_then(|item| item.get("optional")).and_then(toml_edit::Item::as_bool)
+        == Some(true)
+}
+
+/// `[name]` and the `[target.'cfg(..)'.name]` tables of `manifest`.
+fn tables_named<'a>(manifest: &'a DocumentMut, name: &'a str) -> impl Iterator<Item = &'a Table> {
+    let targets =
+        manifest.get("target").and_then(|item| item.as_table()).into_iter().flat_map(
+            move |targets| targets.iter().filter_map(move |(_, target)| target.get(name)),
+        );
+    manifest.get(name).into_iter().chain(targets).filter_map(|item| item.as_table())
+}
+
+/// Whether `[name]` or a target specific variant of it has an `optional = true` dependency.
+pub fn has_optional_dependency(manifest: &DocumentMut, name: &str) -> bool {
+    tables_named(manifest, name).any(|table| table.iter().any(|(key, _)| is_optional(table, key)))
+}
+
+/// Removes `[name]` and its target specific variants, `false` if there are none.
+pub fn remove_dependency_tables(manifest: &mut DocumentMut, name: &str) -> bool {
+    let mut removed = manifest.remove(name).is_some();
+    if let Some(targets) = manifest.get_mut("target").and_then(|item| item.as_table_mut()) {
+        for (_, target) in targets.iter_mut() {
+            if let Some(target) = target.as_table_mut() {
+                removed |= target.remove(name).is_some();
+            }
+        }
+        targets.retain(|_, target| target.as_table().is_none_or(|target| !target.is_empty()));
+    }
+    removed
+}
+
+/// Adds `names` to `ignored` in `[package.metadata.cargo-shear]` or, with `section` `workspace`,
+/// `[workspace.metadata.cargo-shear]`, creating them as needed; listed names are not repeated.
+pub fn add_ignored(
+    manifest: &mut DocumentMut,
+    section: &str,
+    names: &[&str],
+) -> Result<(), String> {
+    let mut table: &mut dyn TableLike = manifest.as_table_mut();
+    for (key, implicit) in [(section, true), ("metadata", true), ("cargo-shear", false)] {
+        let item = table.entry(key).or_insert_with(|| {
+            let mut table = Table::new();
+            table.set_implicit(implicit);
+            Item::Table(table)
+        });
+        table = item.as_table_like_mut().ok_or_else(|| format!("`{key}` is not a table"))?;
+    }
+    let ignored = table
+        .entry("ignored")
+        .or_insert_with(|| Item::Value(Array::new().into()))
+        .as_array_mut()
+        .ok_or_else(|| "`ignored` is not an array".to_string())?;
+    for name in names {
+        if !ignored.iter().any(|value| value.as_str() == Some(name)) {
+            ignored.push(*name);
+        }
+    }
+    Ok(())
+}
+
+/// The first declaration of the dependency on `package_name` in `manifest`, including renamed,
+/// target specific and `[workspace.dependencies]` ones.
+pub fn dependency_location(manifest: &str, package_name: &str) -> Option<Location> {
+    let document = ImDocument::parse(manifest).ok()?;
+    let table_names = ["dependencies", "dev-dependencies", "build-dependencies"];
+    let targets = document
+        .get("target")
+        .and_then(|item| item.as_table_like())
+        .into_iter()
+        .flat_map(|targets| targets.iter().map(|(_, target)| target));
+    let mut tables = table_names
+        .iter()
+        .filter_map(|name| document.get(name))
+        .chain(targets.flat_map(|target| table_names.iter().filter_map(|name| target.get(name))))
+        .chain(document.get("workspace").and_then(|workspace| workspace.get("dependencies")))
+        .filter_map(|item| item.as_table_like());
+    let span = tables.find_map(|table| {
+        let (key, _) = table.iter().find(|(key, item)| {
+            item.get("package").and_then(|item| item.as_str()).unwrap_or(key) == package_name
+        })?;
+        table.get_key_value(key)?.0.span()
+    })?;
+    Some(Location::new(manifest, span.start))
+}
+
+/// The `[name]` header in `manifest`.
+pub fn table_location(manifest: &str, name: &str) -> Option<Location> {
+    let document = ImDocument::parse(manifest).ok()?;
+    let table = document.get(name)?.as_table()?;
+    // Tables only declared through `[name.dependency]` headers or dotted keys have no span.
+    let span = table.span().or_else(|| table.iter().find_map(|(key, _)| table.key(key)?.span()))?;
+    Some(Location::new(manifest, span.start))
+}
+
+/// Collapses runs of blank lines between the entries of `table` and drops blank lines directly
+/// below its header, e.g. left behind by a removed dependency and its comments.
+pub fn tidy_blank_lines(table: &mut Table) {
+    for (index, (mut key, _)) in table.iter_mut().enumerate() {
+        let decor = key.leaf_decor_mut();
+        let Some(prefix) = decor.prefix().and_then(|prefix| prefix.as_str()) else { continue };
+        let tidy = collapse_blank_lines(prefix, index == 0);
+        if tidy != prefix {
+            decor.set_prefix(tidy);
+        }
+    }
+}
+
+/// At most one blank line in a row, none at the start if `first`.
+fn collapse_blank_lines(prefix: &str, first: bool) -> String {
+    let mut tidy = String::with_capacity(prefix.len());
+    let mut previous_blank = first;
+    for line in prefix.split_inclusive('\n') {
+        let blank = line.ends_with('\n') && line.trim().is_empty();
+        if !(blank && previous_blank) {
+            tidy.push_str(line);
+        }
+        previous_blank = blank;
+    }
+    tidy
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use toml_edit::DocumentMut;
+
+    use crate::output::Location;
+
+    use super::{
+        add_ignored, dependency_location, empty_dependency_tables, remove_dependency_tables,
+        table_location, tidy_blank_lines, version_requirements, EditOp, EditPlan, EditStyle,
+    };
+
+    #[test]
+    fn tidy_after_removal() {
+        let mut manifest = DocumentMut::from_str(
+            "[dependencies]\n# Unused\nryu = \"1\"\n\n\n# Used\nitoa = \"1\"\n\n\nserde = \"1\"\n",
+        )
+        .unwrap();
+        let table = manifest["dependencies"].as_table_mut().unwrap();
+        table.remove("ryu");
+        tidy_blank_lines(table);
+        assert_eq!(manifest.to_string(), "[dependencies]\n# Used\nitoa = \"1\"\n\nserde = \"1\"\n");
+    }
+
+    #[test]
+    fn remove_tables() {
+        let source = "[package]\nname = \"a\"\n\n[build-dependencies]\ncc = \"1\"\n\n[target.'cfg(unix)'.build-dependencies]\npkg-config = \"0.3\"\n";
+        let mut manifest = DocumentMut::from_str(source).unwrap();
+        assert_eq!(
+            table_location(source, "build-dependencies").map(|location| location.line),
+            Some(4)
+        );
+        assert!(remove_dependency_tables(&mut manifest, "build-dependencies"));
+        assert_eq!(manifest.to_string(), "[package]\nname = \"a\"\n");
+        assert!(!remove_dependency_tables(&mut manifest, "build-dependencies"));
+
+        let source = "[package]\nname = \"a\"\n\n[build-dependencies.cc]\nversion = \"1\"\n";
+        assert_eq!(
+            table_location(source, "build-dependencies").map(|location| location.line),
+            Some(4)
+        );
+        let mut manifest = DocumentMut::from_str(source).unwrap();
+        assert!(remove_dependency_tables(&mut manifest, "build-dependencies"));
+        assert_eq!(manifest.to_string(), "[package]\nname = \"a\"\n");
+    }
+
+    #[test]
+    fn edit_plan() {
+        let source = "[package]\nname = \"a\"\n\n[dependencies]\nitoa = \"1\"\nryu = { version = \"1\", optional = true }\n\n[build-dependencies]\ncc = \"1\"\n";
+        let manifest = DocumentMut::from_str(source).unwrap();
+        let mut plan = EditPlan::default();
+        assert_eq!(plan.plan(&manifest, EditOp::RemoveDependency("itoa".to_string())), Ok(()));
+        assert_eq!(
+            plan.plan(&manifest, EditOp::RemoveDependency("ryu".to_string())),
+            Err("optional".to_string())
+        );
+        assert_eq!(
+            plan.plan(&manifest, EditOp::RemoveDependency("serde".to_string())),
+            Err("not in a `[dependencies]` table".to_string())
+        );
+        assert_eq!(plan.plan(&manifest, EditOp::RemoveTable("build-dependencies")), Ok(()));
+        assert_eq!(
+            plan.plan(&manifest, EditOp::RemoveTable("dev-dependencies")),
+            Err("no `[dev-dependencies]` table".to_string())
+        );
+
+        let mut applied = manifest.clone();
+        plan.apply(&mut applied, EditStyle::default());
+        assert_eq!(
+            applied.to_string(),
+            "[package]\nname = \"a\"\n\n[dependencies]\nryu = { version = \"1\", optional = true }\n"
+        );
+        assert_eq!(manifest.to_string(), source);
+    }
+
+    #[test]
+    fn dotted_keys() {
+        for (source, line, expected) in [
+            (
+                "dependencies.itoa.version = \"1\"\ndependencies.ryu.version = \"1\"\n",
+                1,
+                "dependencies.ryu.version = \"1\"\n",
+            ),
+            (
+                "[dependencies]\nitoa.version = \"1\"\nitoa.features = [\"std\"]\nryu = \"1\"\n",
+                2,
+                "[dependencies]\nryu = \"1\"\n",
+            ),
+            (
+                "[package]\nname = \"a\"\n\n[dependencies.itoa]\nversion = \"1\"\n\n[dependencies.ryu]\nversion = \"1\"\n",
+                4,
+                "[package]\nname = \"a\"\n\n[dependencies.ryu]\nversion = \"1\"\n",
+            ),
+            (
+                "[dependencies]\nryu = \"1\"\n\n[dependencies.itoa]\nversion = \"1\"\n",
+                4,
+                "[dependencies]\nryu = \"1\"\n",
+            ),
+        ] {
+            assert_eq!(dependency_location(source, "itoa").map(|location| location.line), Some(line));
+            let mut manifest = DocumentMut::from_str(source).unwrap();
+            let mut plan = EditPlan::default();
+            plan.plan(&manifest, EditOp::RemoveDependency("itoa".to_string())).unwrap();
+            plan.apply(&mut manifest, EditStyle::default());
+            assert_eq!(manifest.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn dependency_locations() {
+        let manifest = "[package]\nname = \"a\"\n\n[target.'cfg(unix)'.dependencies]\nssl = { package = \"openssl\", version = \"0.10\" }\n";
+        assert_eq!(
+            dependency_location(manifest, "openssl"),
+            Some(Location { offset: 56, line: 5, column: 1 })
+        );
+        let manifest = "[workspace.dependencies]\nitoa = \"1\"\n";
+        assert_eq!(
+            dependency_location(manifest, "itoa"),
+            Some(Location { offset: 25, line: 2, column: 1 })
+        );
+    }
+
+    #[test]
+    fn empty_tables() {
+        let source = "[package]\nname = \"a\"\n\n[dependencies]\nitoa = \"1\"\n\n[dev-dependencies]\n\n[target.'cfg(unix)'.dependencies]\nryu = \"1\"\n\n[target.'cfg(unix)'.dev-dependencies]\nryu = \"1\"\n";
+        assert_eq!(
+            empty_dependency_tables(source),
+            [("dev-dependencies".to_string(), Location { offset: 49, line: 7, column: 1 })]
+        );
+
+        let mut manifest = DocumentMut::from_str(source).unwrap();
+        let mut plan = EditPlan::default();
+        for name in ["itoa", "ryu"] {
+            plan.plan(&manifest, EditOp::RemoveDependency(name.to_string())).unwrap();
+        }
+        plan.apply(&mut manifest, EditStyle::default());
+        // Only the tables emptied by the plan are removed.
+        assert_eq!(manifest.to_string(), "[package]\nname = \"a\"\n\n[dev-dependencies]\n");
+
+        let source = "[package]\nname = \"a\"\n\n[target.'cfg(unix)'.dependencies]\n";
+        assert_eq!(
+            empty_dependency_tables(source),
+            [(
+                "target.'cfg(unix)'.dependencies".to_string(),
+                Location { offset: 22, line: 4, column: 1 }
+            )]
+        );
+    }
+
+    #[test]
+    fn ignored() {
+        let mut manifest =
+            DocumentMut::from_str("[package]\nname = \"a\"\n\n[dependencies]\nitoa = \"1\"\n")
+                .unwrap();
+        add_ignored(&mut manifest, "package", &["itoa"]).unwrap();
+        add_ignored(&mut manifest, "package", &["itoa", "ryu"]).unwrap();
+        assert_eq!(
+            manifest.to_string(),
+            "[package]\nname = \"a\"\n\n[package.metadata.cargo-shear]\nignored = [\"itoa\", \"ryu\"]\n\n\
+             [dependencies]\nitoa = \"1\"\n"
+        );
+
+        let mut manifest = DocumentMut::from_str(
+            "[workspace]\nmembers = [\"a\"]\n\n[workspace.metadata.cargo-shear]\nignored = \"ryu\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            add_ignored(&mut manifest, "workspace", &["itoa"]),
+            Err("`ignored` is not an array".to_string())
+        );
+    }
+
+    #[test]
+    fn requirements() {
+        let manifest = "[dependencies]\nitoa = \"*\"\njson = { package = \"serde_json\", version = \">=1\" }\nryu.workspace = true\nlocal = { path = \"../local\" }\n\n[workspace.dependencies]\nryu = \"1\"\n";
+        let requirements = version_requirements(manifest)
+            .into_iter()
+            .map(|(name, req, location)| (name, req, location.line))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            requirements,
+            [
+                ("itoa".to_string(), "*".to_string(), 2),
+                ("serde_json".to_string(), ">=1".to_string(), 3),
+                ("ryu".to_string(), "1".to_string(), 8),
+            ]
+        );
+    }
+}
@@ -0,0 +1,100 @@
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use cargo_metadata::Metadata;
+use serde::{Deserialize, Serialize};
+
+use crate::{content_hash, discovery, file_provider::RealFileProvider, VERSION};
+
+/// `cargo metadata` output for `--metadata-cache`, valid while the manifests and the lockfile
+/// it was computed from are unchanged.
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    version: String,
+
+    cargo_args: Vec<String>,
+
+    /// Content hashes of the manifests of all path packages, and of `Cargo.lock`.
+    files: BTreeMap<PathBuf, u64>,
+
+    metadata: Metadata,
+}
+
+/// `target/cargo-shear/metadata.json` of the workspace at `path`, or under `CARGO_TARGET_DIR`.
+pub fn cache_path(path: &Path) -> PathBuf {
+    env::var_os("CARGO_TARGET_DIR")
+        .map_or_else(|| path.join("target"), PathBuf::from)
+        .join("cargo-shear")
+        .join("metadata.json")
+}
+
+/// The cached metadata, unless a file it was computed from changed or a member was added.
+pub fn load(cache_path: &Path, cargo_args: &[String]) -> Option<Metadata> {
+    let entry = serde_json::from_str::<CacheEntry>(&fs::read_to_string(cache_path).ok()?).ok()?;
+    if entry.version != VERSION || entry.cargo_args != cargo_args {
+        return None;
+    }
+    let unchanged = entry.files.iter().all(|(path, hash)| {
+        fs::read_to_string(path).is_ok_and(|content| content_hash(&content) == *hash)
+    });
+    // Members matched by a glob can be added without changing any manifest.
+    let root = entry.metadata.workspace_root.as_std_path();
+    let members = discovery::package_dirs(root, &RealFileProvider).ok()?;
+    let known_members = members.iter().all(|dir| entry.files.contains_key(&dir.join("Cargo.toml")));
+    (unchanged && known_members).then_some(entry.metadata)
+}
+
+/// Caches `metadata`; failures only cost the next run a `cargo metadata` call, so they are ignored.
+pub fn store(cache_path: &Path, cargo_args: &[String], metadata: &Metadata) {
+    let lockfile = metadata.workspace_root.join("Cargo.lock").into_std_path_buf();
+    let files = metadata
+        .packages
+        .iter()
+        .filter(|package| package.source.is_none())
+        .map(|package| package.manifest_path.as_std_path().to_path_buf())
+        .chain([metadata.workspace_root.join("Cargo.toml").into_std_path_buf(), lockfile])
+        .filter_map(|path| {
+            let hash = content_hash(&fs::read_to_string(&path).ok()?);
+            Some((path, hash))
+        })
+        .collect();
+    let entry = CacheEntry {
+        version: VERSION.to_string(),
+        cargo_args: cargo_args.to_vec(),
+        files,
+        metadata: metadata.clone(),
+    };
+    let Ok(json) = serde_json::to_string(&entry) else { return };
+    if let Some(dir) = cache_path.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    let _ = fs::write(cache_path, json);
+}
+
+#[cfg(test)]
+mod tests {
+    use cargo_metadata::MetadataCommand;
+
+    use super::{load, store};
+
+    #[test]
+    fn round_trip() {
+        let metadata =
+            MetadataCommand::new().current_dir(env!("CARGO_MANIFEST_DIR")).exec().unwrap();
+        let cache_path = std::env::temp_dir()
+            .join(format!("cargo-shear-metadata-{}", std::process::id()))
+            .join("metadata.json");
+        assert!(load(&cache_path, &[]).is_none());
+
+        store(&cache_path, &[], &metadata);
+        let cached = load(&cache_path, &[]).unwrap();
+        assert_eq!(cached.workspace_root, metadata.workspace_root);
+        assert_eq!(cached.packages.len(), metadata.packages.len());
+        assert!(load(&cache_path, &["--offline".to_string()]).is_none());
+
+        std::fs::remove_dir_all(cache_path.parent().unwrap()).unwrap();
+    }
+}
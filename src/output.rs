@@ -0,0 +1,832 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{self, Write},
+    str::FromStr,
+    time::Duration,
+};
+
+use serde::Serialize;
+use similar::{DiffTag, TextDiff};
+
+use crate::config::line_column;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Human,
+    Markdown,
+    Short,
+    Json,
+    JsonLines,
+    Toml,
+    Triage,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(Self::Human),
+            "markdown" => Ok(Self::Markdown),
+            "short" => Ok(Self::Short),
+            "json" => Ok(Self::Json),
+            "json-lines" => Ok(Self::JsonLines),
+            "toml" => Ok(Self::Toml),
+            "triage" => Ok(Self::Triage),
+            _ => Err(format!(
+                "expected `human`, `markdown`, `short`, `json`, `json-lines`, `toml` or `triage`, \
+                 found `{s}`"
+            )),
+        }
+    }
+}
+
+/// How `--fix` handled an unused dependency.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "outcome", content = "reason", rename_all = "snake_case")]
+pub enum FixOutcome {
+    Fixed,
+
+    /// Added to `ignored` by `--fix-suppress`.
+    Suppressed,
+    Skipped(String),
+    Failed(String),
+}
+
+/// A position in a manifest, as a byte offset and as a 1-based line and column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Location {
+    pub offset: usize,
+
+    pub line: usize,
+
+    /// In bytes, like `offset`.
+    pub column: usize,
+}
+
+impl Location {
+    /// The location of the byte `offset` of `source`.
+    #[must_use]
+    pub fn new(source: &str, offset: usize) -> Self {
+        let (line, column) = line_column(source, offset);
+        Self { offset, line, column }
+    }
+}
+
+/// `line:column`.
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// The probable reason an unused dependency is declared, to triage large reports.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Category {
+    /// A proc-macro crate, possibly used by macro expansions the analysis does not see.
+    MacroOnly,
+
+    /// Enables features, possibly only to unify them with the dependencies of other crates.
+    FeatureOnly,
+
+    /// Only a dev-dependency.
+    DevOnly,
+
+    /// None of the above.
+    #[default]
+    Unused,
+}
+
+impl Category {
+    const fn description(self) -> &'static str {
+        match self {
+            Self::MacroOnly => "likely macro-only",
+            Self::FeatureOnly => "feature-only",
+            Self::DevOnly => "dev-only",
+            Self::Unused => "unused",
+        }
+    }
+}
+
+/// An unused dependency, by package name.
+#[derive(Debug, Clone, Serialize)]
+pub struct Finding {
+    pub dependency: String,
+
+    /// Kind of finding, one of the `--fix-only` codes.
+    pub code: &'static str,
+
+    pub category: Category,
+
+    /// Where the dependency is declared in the manifest, if found.
+    pub location: Option<Location>,
+
+    /// The edits `--fix` would make to the manifest for this finding alone, empty if it cannot
+    /// be fixed. Findings of a table removed as a whole share the same edits.
+    pub edits: Vec<TextEdit>,
+
+    /// `None` unless `--fix` was used.
+    pub fix: Option<FixOutcome>,
+}
+
+impl Finding {
+    #[must_use]
+    pub const fn new(dependency: String) -> Self {
+        Self {
+            dependency,
+            code: "unused_dependency",
+            category: Category::Unused,
+            location: None,
+            edits: Vec::new(),
+            fix: None,
+        }
+    }
+
+    #[must_use]
+    pub fn is_fixed(&self) -> bool {
+        self.fix == Some(FixOutcome::Fixed)
+    }
+
+    /// Whether the dependency was removed or, with `--fix-suppress`, ignored.
+    #[must_use]
+    pub const fn is_resolved(&self) -> bool {
+        matches!(self.fix, Some(FixOutcome::Fixed | FixOutcome::Suppressed))
+    }
+
+    /// Status column of the markdown table, also used as a suffix in human output.
+    #[must_use]
+    pub fn status(&self) -> String {
+        match &self.fix {
+            None => "unused".to_string(),
+            Some(FixOutcome::Fixed) => "removed".to_string(),
+            Some(FixOutcome::Suppressed) => "ignored".to_string(),
+            Some(FixOutcome::Skipped(reason)) => format!("skipped: {reason}"),
+            Some(FixOutcome::Failed(error)) => format!("failed: {error}"),
+        }
+    }
+}
+
+/// A replacement of whole lines of a manifest, in the shape of an LSP `TextEdit`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TextEdit {
+    /// Manifest path relative to the workspace root.
+    pub path: String,
+
+    pub range: TextRange,
+
+    #[serde(rename = "newText")]
+    pub new_text: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct TextRange {
+    pub start: TextPosition,
+
+    /// Exclusive.
+    pub end: TextPosition,
+}
+
+/// 0-based, like LSP positions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct TextPosition {
+    pub line: usize,
+
+    pub character: usize,
+}
+
+impl TextEdit {
+    /// The line edits turning `original` into `modified`.
+    #[must_use]
+    pub fn diff(path: &str, original: &str, modified: &str) -> Vec<Self> {
+        let diff = TextDiff::from_lines(original, modified);
+        let line = |line| TextPosition { line, character: 0 };
+        diff.ops()
+            .iter()
+            .filter(|op| op.tag() != DiffTag::Equal)
+            .map(|op| Self {
+                path: path.to_string(),
+                range: TextRange {
+                    start: line(op.old_range().start),
+                    end: line(op.old_range().end),
+                },
+                new_text: diff.new_slices()[op.new_range()].concat(),
+            })
+            .collect()
+    }
+}
+
+/// Unused dependencies of a package, or of the workspace root.
+#[derive(Debug, Clone, Serialize)]
+pub struct PackageReport {
+    /// Package name, `root` for `[workspace.dependencies]`.
+    pub name: String,
+
+    /// Manifest path relative to the workspace root.
+    pub manifest_path: String,
+
+    pub unused_dependencies: Vec<Finding>,
+
+    /// Why all of `unused_dependencies` are unused, if they are reported as a whole table.
+    pub reason: Option<String>,
+
+    /// Whether the package is in the workspace's `default-members`; always true for `root`.
+    pub default_member: bool,
+
+    /// Whether `unused_dependencies` are in `[workspace.dependencies]` rather than in the
+    /// dependencies of a package.
+    pub workspace: bool,
+}
+
+/// How many workspace members were analyzed, and why the others were not.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Coverage {
+    pub analyzed: usize,
+
+    /// Number of packages which were not analyzed, by reason: `excluded`, `not_default` with
+    /// `--default-members`, `failed`, `not_reached` after `--fail-fast` stopped, or `workspace_only`.
+    pub skipped: BTreeMap<&'static str, usize>,
+}
+
+impl Coverage {
+    pub fn skip(&mut self, reason: &'static str, count: usize) {
+        if count > 0 {
+            *self.skipped.entry(reason).or_default() += count;
+        }
+    }
+
+    #[must_use]
+    pub fn total(&self) -> usize {
+        self.analyzed + self.skipped.values().sum::<usize>()
+    }
+
+    /// e.g. `Analyzed 12 of 15 packages (3 excluded).`
+    #[must_use]
+    pub fn summary(&self) -> String {
+        let mut summary = format!("Analyzed {} of {} packages", self.analyzed, self.total());
+        if !self.skipped.is_empty() {
+            let reasons = self
+                .skipped
+                .iter()
+                .map(|(reason, count)| format!("{count} {}", reason.replace('_', " ")))
+                .collect::<Vec<_>>();
+            let _ = write!(summary, " ({})", reasons.join(", "));
+        }
+        summary.push('.');
+        summary
+    }
+}
+
+/// How long files took to parse and packages to analyze, for `--profile-parse`.
+#[derive(Debug, Clone, Default)]
+pub struct ParseProfile {
+    /// By path relative to the workspace root.
+    pub files: Vec<(String, Duration)>,
+
+    pub packages: Vec<(String, Duration)>,
+}
+
+impl ParseProfile {
+    /// The `limit` slowest files and packages, slowest first.
+    #[must_use]
+    pub fn render(&self, limit: usize) -> String {
+        let mut out = String::new();
+        for (title, times) in [
+            ("Slowest files to parse", &self.files),
+            ("Slowest packages to analyze", &self.packages),
+        ] {
+            let mut times = times.iter().collect::<Vec<_>>();
+            times.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+            let _ = writeln!(out, "{title}:");
+            for (name, time) in times.into_iter().take(limit) {
+                let _ = writeln!(out, "  {:>9}  {name}", format!("{time:.1?}"));
+            }
+        }
+        out.truncate(out.trim_end().len());
+        out
+    }
+}
+
+/// Renders a summary suitable for a pull request body.
+pub fn render_markdown(
+    reports: &[PackageReport],
+    coverage: &Coverage,
+    errors: &[String],
+    warnings: &[String],
+) -> String {
+    let mut out = String::from("## cargo-shear\n\n");
+
+    // Partial runs are called out, since they may miss unused dependencies.
+    if !coverage.skipped.is_empty() {
+        let _ = writeln!(out, "{}\n", coverage.summary());
+    }
+
+    if reports.is_empty() {
+        out.push_str("No unused dependencies!\n\n");
+    }
+
+    for report in reports {
+        let _ = writeln!(out, "### `{}` (`{}`)\n", report.name, report.manifest_path);
+        if let Some(reason) = &report.reason {
+            let _ = writeln!(out, "{reason}\n");
+        }
+        out.push_str("| Dependency | Status |\n| --- | --- |\n");
+        for finding in &report.unused_dependencies {
+            let _ = writeln!(out, "| `{}` | {} |", finding.dependency, finding.status());
+        }
+        out.push('\n');
+    }
+
+    if !errors.is_empty() {
+        out.push_str("### Errors\n\n");
+        for error in errors {
+            let _ = writeln!(out, "- {error}");
+        }
+        out.push('\n');
+    }
+
+    if !warnings.is_empty() {
+        out.push_str("### Warnings\n\n");
+        for warning in warnings {
+            let _ = writeln!(out, "- {warning}");
+        }
+        out.push('\n');
+    }
+
+    if let Some(suppressions) = render_suppressions(reports) {
+        let _ = write!(
+            out,
+            "### False positives\n\n\
+             Dependencies detected incorrectly can be ignored in their Cargo.toml:\n\n\
+             ```toml\n{suppressions}```\n",
+        );
+    }
+
+    out.truncate(out.trim_end().len());
+    out.push('\n');
+    out
+}
+
+/// The number of unused dependencies by [`Category`], e.g.
+/// `By probable cause: 3 unused, 1 likely macro-only.`, or `None` without any.
+pub fn render_categories(reports: &[PackageReport]) -> Option<String> {
+    let mut counts = BTreeMap::<Category, usize>::new();
+    for finding in reports
+        .iter()
+        .flat_map(|report| &report.unused_dependencies)
+        .filter(|finding| !finding.is_resolved())
+    {
+        *counts.entry(finding.category).or_default() += 1;
+    }
+    if counts.is_empty() {
+        return None;
+    }
+    let counts = counts
+        .into_iter()
+        .map(|(category, count)| format!("{count} {}", category.description()))
+        .collect::<Vec<_>>()
+        .join(", ");
+    Some(format!("By probable cause: {counts}."))
+}
+
+/// An action plan for the unused dependencies, safest first: removals `--fix` can make, then
+/// removals to check first by [`Category`], then the dependencies to review by hand.
+pub fn render_triage(reports: &[PackageReport]) -> String {
+    let mut tiers = [vec![], vec![], vec![]];
+    for report in reports {
+        for finding in report.unused_dependencies.iter().filter(|finding| !finding.is_resolved()) {
+            let location = finding.location.map_or_else(
+                || report.manifest_path.clone(),
+                |location| format!("{}:{location}", report.manifest_path),
+            );
+            let dependency = format!("`{}` of `{}` at {location}", finding.dependency, report.name);
+            let (tier, action) = match (finding.edits.is_empty(), finding.category) {
+                (true, _) => (2, "edit the manifest by hand, `--fix` cannot remove it".to_string()),
+                (false, Category::MacroOnly) => (
+                    1,
+                    "check that no macro expansion uses it, e.g. with `cargo expand`, then remove it"
+                        .to_string(),
+                ),
+                (false, Category::FeatureOnly) => (
+                    1,
+                    "check that no other crate relies on the features it enables, then remove it"
+                        .to_string(),
+                ),
+                (false, Category::DevOnly | Category::Unused) => {
+                    let lines = finding.edits.iter().map(|edit| {
+                        let range = &edit.range;
+                        if range.end.line == range.start.line + 1 {
+                            format!("line {}", range.start.line + 1)
+                        } else {
+                            format!("lines {}-{}", range.start.line + 1, range.end.line)
+                        }
+                    });
+                    (0, format!("remove it, `--fix` edits {}", lines.collect::<Vec<_>>().join(", ")))
+                }
+            };
+            tiers[tier].push(format!("  - {dependency}: {action}"));
+        }
+    }
+    if tiers.iter().all(Vec::is_empty) {
+        return "No unused dependencies!\n".to_string();
+    }
+
+    let titles = ["Safe to remove", "Check, then remove", "Review by hand"];
+    let mut out = String::new();
+    for (step, (title, items)) in
+        titles.into_iter().zip(tiers).filter(|(_, items)| !items.is_empty()).enumerate()
+    {
+        let _ = writeln!(out, "{}. {title} ({}):", step + 1, items.len());
+        for item in items {
+            let _ = writeln!(out, "{item}");
+        }
+        out.push('\n');
+    }
+    out.push_str(
+        "`cargo shear --fix` makes all the removals at once, `--fix-only <CODE>` selects them by code.\n",
+    );
+    if let Some(suppressions) = suppressions(reports, |finding| {
+        !finding.edits.is_empty()
+            && matches!(finding.category, Category::MacroOnly | Category::FeatureOnly)
+    }) {
+        let _ = write!(
+            out,
+            "\nTo keep the dependencies to check instead, ignore them:\n\n{suppressions}"
+        );
+    }
+    out
+}
+
+/// The `ignored` entries which silence the findings of `reports` which are not fixed, as a
+/// `cargo-shear` metadata table per manifest after a comment with its path, or `None` without any.
+pub fn render_suppressions(reports: &[PackageReport]) -> Option<String> {
+    suppressions(reports, |_| true)
+}
+
+/// Like [`render_suppressions`], for the findings matching `filter`.
+fn suppressions(reports: &[PackageReport], filter: impl Fn(&Finding) -> bool) -> Option<String> {
+    // The root manifest can have both tables.
+    let mut tables = BTreeMap::<(&str, &str), BTreeSet<&str>>::new();
+    for report in reports {
+        let table = if report.workspace { "workspace" } else { "package" };
+        let dependencies = report
+            .unused_dependencies
+            .iter()
+            .filter(|finding| !finding.is_resolved() && filter(finding))
+            .map(|finding| finding.dependency.as_str())
+            .collect::<Vec<_>>();
+        if !dependencies.is_empty() {
+            tables.entry((&report.manifest_path, table)).or_default().extend(dependencies);
+        }
+    }
+    if tables.is_empty() {
+        return None;
+    }
+
+    let mut out = String::new();
+    for (step, ((manifest_path, table), dependencies)) in tables.into_iter().enumerate() {
+        if step > 0 {
+            out.push('\n');
+        }
+        let dependencies = dependencies.iter().map(|dependency| format!("\"{dependency}\""));
+        let _ = writeln!(
+            out,
+            "# {manifest_path}\n[{table}.metadata.cargo-shear]\nignored = [{}]",
+            dependencies.collect::<Vec<_>>().join(", ")
+        );
+    }
+    Some(out)
+}
+
+/// One `file:line:col: code: message` line per finding, like `rustc --error-format short`.
+pub fn render_short(reports: &[PackageReport], errors: &[String], warnings: &[String]) -> String {
+    let mut out = String::new();
+    for report in reports {
+        for finding in &report.unused_dependencies {
+            let (line, column) =
+                finding.location.map_or((1, 1), |location| (location.line, location.column));
+            let _ = writeln!(
+                out,
+                "{}:{line}:{column}: {}: `{}` of `{}` is {}",
+                report.manifest_path,
+                finding.code,
+                finding.dependency,
+                report.name,
+                finding.status()
+            );
+        }
+    }
+    for error in errors {
+        let _ = writeln!(out, "error: {error}");
+    }
+    for warning in warnings {
+        let _ = writeln!(out, "warning: {warning}");
+    }
+    out
+}
+
+/// A line of `--format json-lines`, printed as soon as it is known so that the output can be
+/// processed while large workspaces are still being analyzed.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JsonLine<'a> {
+    /// A [`Finding`] with the fields of its [`PackageReport`] other than `unused_dependencies`.
+    UnusedDependency {
+        package: &'a str,
+        manifest_path: &'a str,
+        reason: Option<&'a str>,
+        default_member: bool,
+        workspace: bool,
+        #[serde(flatten)]
+        finding: &'a Finding,
+    },
+    Error {
+        message: &'a str,
+    },
+    Warning {
+        message: &'a str,
+    },
+    /// The last line.
+    Summary {
+        unused_dependencies: usize,
+        coverage: &'a Coverage,
+    },
+}
+
+impl fmt::Display for JsonLine<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&serde_json::to_string(self).map_err(|_| fmt::Error)?)
+    }
+}
+
+/// A shields.io endpoint payload, see <https://shields.io/badges/endpoint-badge>.
+pub fn render_badge(unused_dependencies: usize) -> String {
+    let color = if unused_dependencies == 0 { "brightgreen" } else { "red" };
+    let badge = serde_json::json!({
+        "schemaVersion": 1,
+        "label": "unused deps",
+        "message": unused_dependencies.to_string(),
+        "color": color,
+    });
+    format!("{badge}\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{
+        render_badge, render_categories, render_markdown, render_short, render_suppressions,
+        render_triage, Category, Coverage, Finding, FixOutcome, JsonLine, Location, PackageReport,
+        ParseProfile, TextEdit,
+    };
+
+    #[test]
+    fn markdown() {
+        let reports = vec![PackageReport {
+            name: "foo".to_string(),
+            manifest_path: "crates/foo/Cargo.toml".to_string(),
+            unused_dependencies: vec![
+                Finding::new("bar".to_string()),
+                Finding {
+                    fix: Some(FixOutcome::Skipped("optional".to_string())),
+                    ..Finding::new("baz".to_string())
+                },
+            ],
+            reason: None,
+            default_member: true,
+            workspace: false,
+        }];
+        let mut coverage = Coverage { analyzed: 1, ..Coverage::default() };
+        coverage.skip("excluded", 2);
+        let markdown = render_markdown(
+            &reports,
+            &coverage,
+            &["`foo` depends on `openssl`, which is banned".to_string()],
+            &["unknown key `ignore`".to_string()],
+        );
+        assert!(markdown.contains("Analyzed 1 of 3 packages (2 excluded).\n\n### `foo`"));
+        assert!(markdown.contains("### `foo` (`crates/foo/Cargo.toml`)\n\n| Dependency | Status |"));
+        assert!(markdown.contains("| `bar` | unused |"));
+        assert!(markdown.contains("| `baz` | skipped: optional |"));
+        assert!(markdown.contains("### Errors\n\n- `foo` depends on `openssl`, which is banned"));
+        assert!(markdown.contains("- unknown key `ignore`"));
+        assert!(markdown.contains(
+            "```toml\n# crates/foo/Cargo.toml\n[package.metadata.cargo-shear]\nignored = [\"bar\", \"baz\"]\n```"
+        ));
+    }
+
+    #[test]
+    fn markdown_no_unused_dependencies() {
+        let markdown = render_markdown(&[], &Coverage::default(), &[], &[]);
+        assert_eq!(markdown, "## cargo-shear\n\nNo unused dependencies!\n");
+    }
+
+    #[test]
+    fn json_lines() {
+        let finding = Finding {
+            location: Some(Location { offset: 42, line: 8, column: 1 }),
+            fix: Some(FixOutcome::Fixed),
+            ..Finding::new("bar".to_string())
+        };
+        let line = JsonLine::UnusedDependency {
+            package: "foo",
+            manifest_path: "foo/Cargo.toml",
+            reason: None,
+            default_member: true,
+            workspace: false,
+            finding: &finding,
+        };
+        assert_eq!(
+            line.to_string(),
+            r#"{"type":"unused_dependency","package":"foo","manifest_path":"foo/Cargo.toml","reason":null,"default_member":true,"workspace":false,"dependency":"bar","code":"unused_dependency","category":"unused","location":{"offset":42,"line":8,"column":1},"edits":[],"fix":{"outcome":"fixed"}}"#
+        );
+        let coverage = Coverage { analyzed: 3, ..Coverage::default() };
+        let line = JsonLine::Summary { unused_dependencies: 1, coverage: &coverage };
+        assert_eq!(
+            line.to_string(),
+            r#"{"type":"summary","unused_dependencies":1,"coverage":{"analyzed":3,"skipped":{}}}"#
+        );
+    }
+
+    #[test]
+    fn categories() {
+        let finding = |category| Finding { category, ..Finding::new("bar".to_string()) };
+        let mut reports = vec![PackageReport {
+            name: "foo".to_string(),
+            manifest_path: "foo/Cargo.toml".to_string(),
+            unused_dependencies: vec![
+                finding(Category::Unused),
+                finding(Category::MacroOnly),
+                finding(Category::Unused),
+                Finding { fix: Some(FixOutcome::Fixed), ..finding(Category::DevOnly) },
+            ],
+            reason: None,
+            default_member: true,
+            workspace: false,
+        }];
+        assert_eq!(
+            render_categories(&reports).as_deref(),
+            Some("By probable cause: 1 likely macro-only, 2 unused.")
+        );
+        reports[0].unused_dependencies.clear();
+        assert_eq!(render_categories(&reports), None);
+    }
+
+    #[test]
+    fn triage() {
+        let original = "[dependencies]\nbar = \"1\"\nbaz = \"1\"\nqux = \"1\"\n";
+        let edits = |dependency: &str| {
+            let modified = original.replace(&format!("{dependency} = \"1\"\n"), "");
+            TextEdit::diff("foo/Cargo.toml", original, &modified)
+        };
+        let reports = vec![PackageReport {
+            name: "foo".to_string(),
+            manifest_path: "foo/Cargo.toml".to_string(),
+            unused_dependencies: vec![
+                Finding {
+                    location: Some(Location { offset: 15, line: 2, column: 1 }),
+                    edits: edits("bar"),
+                    ..Finding::new("bar".to_string())
+                },
+                Finding {
+                    category: Category::MacroOnly,
+                    edits: edits("baz"),
+                    ..Finding::new("baz".to_string())
+                },
+                Finding::new("qux".to_string()),
+            ],
+            reason: None,
+            default_member: true,
+            workspace: false,
+        }];
+        assert_eq!(
+            render_triage(&reports),
+            "1. Safe to remove (1):\n  \
+             - `bar` of `foo` at foo/Cargo.toml:2:1: remove it, `--fix` edits line 2\n\n\
+             2. Check, then remove (1):\n  \
+             - `baz` of `foo` at foo/Cargo.toml: check that no macro expansion uses it, \
+             e.g. with `cargo expand`, then remove it\n\n\
+             3. Review by hand (1):\n  \
+             - `qux` of `foo` at foo/Cargo.toml: edit the manifest by hand, `--fix` cannot remove it\n\n\
+             `cargo shear --fix` makes all the removals at once, `--fix-only <CODE>` selects them by code.\n\n\
+             To keep the dependencies to check instead, ignore them:\n\n\
+             # foo/Cargo.toml\n\
+             [package.metadata.cargo-shear]\n\
+             ignored = [\"baz\"]\n"
+        );
+        assert_eq!(render_triage(&[]), "No unused dependencies!\n");
+    }
+
+    #[test]
+    fn suppressions() {
+        let report = |name: &str, manifest_path: &str, findings: Vec<Finding>| PackageReport {
+            name: name.to_string(),
+            manifest_path: manifest_path.to_string(),
+            unused_dependencies: findings,
+            reason: None,
+            default_member: true,
+            workspace: false,
+        };
+        let reports = vec![
+            PackageReport {
+                workspace: true,
+                ..report("root", "Cargo.toml", vec![Finding::new("serde".to_string())])
+            },
+            // A member named `root`.
+            report("root", "root/Cargo.toml", vec![Finding::new("libc".to_string())]),
+            report(
+                "foo",
+                "foo/Cargo.toml",
+                vec![
+                    Finding::new("ryu".to_string()),
+                    Finding { fix: Some(FixOutcome::Fixed), ..Finding::new("bar".to_string()) },
+                ],
+            ),
+            report("foo", "foo/Cargo.toml", vec![Finding::new("itoa".to_string())]),
+        ];
+        assert_eq!(
+            render_suppressions(&reports).unwrap(),
+            "# Cargo.toml\n\
+             [workspace.metadata.cargo-shear]\n\
+             ignored = [\"serde\"]\n\n\
+             # foo/Cargo.toml\n\
+             [package.metadata.cargo-shear]\n\
+             ignored = [\"itoa\", \"ryu\"]\n\n\
+             # root/Cargo.toml\n\
+             [package.metadata.cargo-shear]\n\
+             ignored = [\"libc\"]\n"
+        );
+        assert_eq!(render_suppressions(&[report("foo", "foo/Cargo.toml", vec![])]), None);
+    }
+
+    #[test]
+    fn short() {
+        let reports = vec![PackageReport {
+            name: "foo".to_string(),
+            manifest_path: "crates/foo/Cargo.toml".to_string(),
+            unused_dependencies: vec![
+                Finding {
+                    location: Some(Location { offset: 42, line: 8, column: 1 }),
+                    ..Finding::new("bar".to_string())
+                },
+                Finding {
+                    code: "unused_dev_dependencies",
+                    fix: Some(FixOutcome::Fixed),
+                    ..Finding::new("baz".to_string())
+                },
+            ],
+            reason: None,
+            default_member: true,
+            workspace: false,
+        }];
+        assert_eq!(
+            render_short(&reports, &[], &["unknown key `ignore`".to_string()]),
+            "crates/foo/Cargo.toml:8:1: unused_dependency: `bar` of `foo` is unused\n\
+             crates/foo/Cargo.toml:1:1: unused_dev_dependencies: `baz` of `foo` is removed\n\
+             warning: unknown key `ignore`\n"
+        );
+    }
+
+    #[test]
+    fn text_edits() {
+        let edits = TextEdit::diff(
+            "Cargo.toml",
+            "[dependencies]\nitoa = \"1\"\nryu = \"1\"\n",
+            "[dependencies]\nitoa = \"1\"\n",
+        );
+        assert_eq!(
+            serde_json::to_value(&edits).unwrap(),
+            serde_json::json!([{
+                "path": "Cargo.toml",
+                "range": { "start": { "line": 2, "character": 0 }, "end": { "line": 3, "character": 0 } },
+                "newText": "",
+            }])
+        );
+        assert!(TextEdit::diff("Cargo.toml", "a\n", "a\n").is_empty());
+    }
+
+    #[test]
+    fn parse_profile() {
+        let profile = ParseProfile {
+            files: vec![
+                ("a/src/lib.rs".to_string(), Duration::from_micros(1500)),
+                ("a/src/generated.rs".to_string(), Duration::from_millis(120)),
+            ],
+            packages: vec![("a".to_string(), Duration::from_millis(125))],
+        };
+        assert_eq!(
+            profile.render(1),
+            "Slowest files to parse:\n    120.0ms  a/src/generated.rs\n\
+             Slowest packages to analyze:\n    125.0ms  a"
+        );
+    }
+
+    #[test]
+    fn badge() {
+        assert_eq!(
+            render_badge(2),
+            "{\"color\":\"red\",\"label\":\"unused deps\",\"message\":\"2\",\"schemaVersion\":1}\n"
+        );
+    }
+}
@@ -0,0 +1,616 @@
+use std::{
+    collections::{hash_map::Entry, HashMap, HashSet},
+    path::{Component, Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+
+use crate::{
+    config::{declared_source, source_kind, ShearConfig},
+    discovery::rust_files_in,
+    file_provider::{FileProvider, RealFileProvider},
+    import_collector::{collect_imports, scan_imports, AttributeCrateRef, Imports, SourceRef},
+    manifest, Deps,
+};
+
+/// Result of [`PackageAnalysisBuilder::analyze`].
+#[derive(Debug)]
+pub struct PackageAnalysis {
+    pub package_name: String,
+
+    /// Package names of the unused dependencies, sorted.
+    pub unused_dependencies: Vec<String>,
+
+    /// Fields of the manifest which could not be parsed and were ignored.
+    pub warnings: Vec<String>,
+}
+
+/// Analyzes a single package from its manifest and an explicit list of source files,
+/// without running `cargo metadata`.
+///
+/// Only `[package.metadata.cargo-shear]` is read; workspace metadata is not discovered.
+/// Dependencies inherited with `workspace = true` are assumed not to be renamed in the workspace.
+pub struct PackageAnalysisBuilder {
+    manifest_path: PathBuf,
+
+    files: Vec<PathBuf>,
+
+    file_provider: Box<dyn FileProvider>,
+}
+
+impl PackageAnalysisBuilder {
+    #[must_use]
+    pub fn new(manifest_path: impl Into<PathBuf>) -> Self {
+        Self {
+            manifest_path: manifest_path.into(),
+            files: vec![],
+            file_provider: Box::new(RealFileProvider),
+        }
+    }
+
+    /// Rust source files to collect imports from.
+    #[must_use]
+    pub fn files(mut self, files: impl IntoIterator<Item = impl Into<PathBuf>>) -> Self {
+        self.files.extend(files.into_iter().map(Into::into));
+        self
+    }
+
+    /// Read the manifest and source files through `file_provider` instead of from disk.
+    #[must_use]
+    pub fn file_provider(mut self, file_provider: impl FileProvider + 'static) -> Self {
+        self.file_provider = Box::new(file_provider);
+        self
+    }
+
+    /// # Errors
+    ///
+    /// When the manifest or a source file cannot be read or parsed.
+    pub fn analyze(self) -> Result<PackageAnalysis> {
+        analyze_manifest(&self.manifest_path, &self.files, self.file_provider.as_ref())
+    }
+}
+
+/// Analyzes the package at `manifest_path` from its manifest alone, see [`PackageAnalysisBuilder`].
+pub fn analyze_manifest(
+    manifest_path: &Path,
+    files: &[PathBuf],
+    file_provider: &dyn FileProvider,
+) -> Result<PackageAnalysis> {
+    analyze_manifest_with(manifest_path, file_provider, |attribute_crate_refs| {
+        collect_package_imports(file_provider, files, DEFAULT_MAX_PARSE_SIZE, attribute_crate_refs)
+    })
+}
+
+/// Like [`analyze_manifest`], with the package's imports from `collect_imports`, which is given the
+/// configured attribute rules.
+pub fn analyze_manifest_with(
+    manifest_path: &Path,
+    file_provider: &dyn FileProvider,
+    collect_imports: impl FnOnce(&[AttributeCrateRef]) -> Result<Imports>,
+) -> Result<PackageAnalysis> {
+    let manifest_source = file_provider.read_to_string(manifest_path)?;
+    let (manifest, warnings) = manifest::parse(&manifest_source)?;
+    let package = manifest
+        .package
+        .as_ref()
+        .with_context(|| format!("{} has no [package]", manifest_path.display()))?;
+
+    let metadata = serde_json::to_value(&package.metadata)?;
+    let config = ShearConfig::from_metadata(&metadata);
+
+    let target_dependencies = manifest.target.values().flat_map(|target| {
+        target.dependencies.iter().chain(&target.dev_dependencies).chain(&target.build_dependencies)
+    });
+    let dependencies = manifest
+        .dependencies
+        .iter()
+        .chain(&manifest.dev_dependencies)
+        .chain(&manifest.build_dependencies)
+        .chain(target_dependencies)
+        .filter(|(_, dependency)| {
+            !config.ignore_sources.contains(source_kind(declared_source(dependency)))
+        })
+        .map(|(key, dependency)| {
+            let package_name = dependency
+                .detail()
+                .and_then(|detail| detail.package.as_ref())
+                .unwrap_or(key)
+                .clone();
+            (key, package_name)
+        })
+        .filter(|(key, name)| config.ignored_entry(key, name, &key.replace('-', "_")).is_none())
+        .map(|(key, name)| (key.replace('-', "_"), name))
+        .collect::<HashMap<_, _>>();
+
+    let imports = collect_imports(&config.attribute_crate_refs)?;
+    let mut unused_dependencies = find_unused_module_names(
+        &dependencies,
+        imports.deps,
+        &config.aliases,
+        &config.known_imports,
+    )
+    .into_iter()
+    .map(|module_name| dependencies[&module_name].clone())
+    .collect::<Vec<_>>();
+    unused_dependencies.sort();
+    unused_dependencies.dedup();
+
+    Ok(PackageAnalysis { package_name: package.name.clone(), unused_dependencies, warnings })
+}
+
+/// Files larger than this many bytes, e.g. `bindgen` output, are scanned instead of parsed.
+pub const DEFAULT_MAX_PARSE_SIZE: usize = 10_000_000;
+
+/// Imports of all `files` of a package.
+pub fn collect_package_imports(
+    file_provider: &dyn FileProvider,
+    files: &[PathBuf],
+    max_parse_size: usize,
+    attribute_crate_refs: &[AttributeCrateRef],
+) -> Result<Imports> {
+    let imports = files
+        .par_iter()
+        .map(|path| process_rust_source(file_provider, path, max_parse_size, attribute_crate_refs))
+        .collect::<Result<Vec<Imports>>>()?;
+    Ok(merge_imports(&imports))
+}
+
+/// Imports of the files reachable from the crate `roots`, e.g. the `src_path` of each target,
+/// through `mod` declarations and `include!`, also returning how long each file took.
+///
+/// Unlike walking the directories of the targets, this skips files which are not part of any
+/// target and finds modules outside of them, e.g. `#[path = "../shared/mod.rs"] mod shared;`.
+pub fn collect_reachable_imports_timed(
+    file_provider: &dyn FileProvider,
+    roots: &[PathBuf],
+    max_parse_size: usize,
+    attribute_crate_refs: &[AttributeCrateRef],
+) -> Result<(Imports, Vec<(PathBuf, Duration)>)> {
+    let mut visited = roots.iter().map(|root| normalize(root)).collect::<HashSet<_>>();
+    let mut imports = vec![];
+    let mut times = vec![];
+    // File -> candidate files of the modules and includes it declares.
+    let mut module_refs = HashMap::new();
+    // Candidate files of each module or include declared by the files.
+    let mut declarations = vec![];
+    // Candidate files, and whether their modules are looked up in their own directory. The files
+    // of the crate roots must exist; a declared module may be missing, e.g. when its `#[cfg]` is
+    // never enabled, and otherwise the first existing candidate is used.
+    let mut pending =
+        roots.iter().map(|root| (vec![(root.clone(), true)], true)).collect::<Vec<_>>();
+    while !pending.is_empty() {
+        let parsed = pending
+            .par_iter()
+            .map(|(candidates, required)| {
+                let start = Instant::now();
+                for (path, owns_dir) in candidates {
+                    let source_text = match file_provider.read_to_string(path) {
+                        Ok(source_text) => source_text,
+                        Err(err) if *required => return Err(err.into()),
+                        Err(_) => continue,
+                    };
+                    let file_imports =
+                        parse_rust_source(&source_text, max_parse_size, attribute_crate_refs)?;
+                    return Ok(Some((path.clone(), *owns_dir, file_imports, start.elapsed())));
+                }
+                Ok(None)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        pending = vec![];
+        for (path, owns_dir, file_imports, time) in parsed.into_iter().flatten() {
+            let mut refs = vec![];
+            for alternatives in referenced_files(&path, owns_dir, &file_imports.source_refs) {
+                let mut declaration = vec![];
+                for mut candidates in alternatives {
+                    let mut new = false;
+                    for (candidate, _) in &mut candidates {
+                        *candidate = normalize(candidate);
+                        new |= visited.insert(candidate.clone());
+                        declaration.push(candidate.clone());
+                    }
+                    if new {
+                        pending.push((candidates, false));
+                    }
+                }
+                refs.extend(declaration.iter().cloned());
+                declarations.push(declaration);
+            }
+            module_refs.insert(normalize(&path), refs);
+            imports.push((normalize(&path), file_imports));
+            times.push((path, time));
+        }
+    }
+    let mut imports = apply_extern_crate_renames(roots, &module_refs, imports);
+    // A declared module which is not found may be loaded from a path that is not followed, e.g.
+    // one built by a macro, so the other files of the targets are scanned too.
+    let parsed = times.iter().map(|(path, _)| normalize(path)).collect::<HashSet<_>>();
+    if declarations.iter().any(|candidates| !candidates.iter().any(|file| parsed.contains(file))) {
+        let (unreached_imports, unreached_times) = collect_unreached_imports(
+            file_provider,
+            roots,
+            &parsed,
+            max_parse_size,
+            attribute_crate_refs,
+        );
+        imports.extend(unreached_imports);
+        times.extend(unreached_times);
+    }
+    Ok((merge_imports(&imports), times))
+}
+
+/// Imports of the `.rs` files in the directories of the crate `roots` which are not `parsed`,
+/// skipping files which cannot be read or parsed.
+fn collect_unreached_imports(
+    file_provider: &dyn FileProvider,
+    roots: &[PathBuf],
+    parsed: &HashSet<PathBuf>,
+    max_parse_size: usize,
+    attribute_crate_refs: &[AttributeCrateRef],
+) -> (Vec<Imports>, Vec<(PathBuf, Duration)>) {
+    let mut files = roots
+        .iter()
+        .filter_map(|root| root.parent())
+        .flat_map(rust_files_in)
+        .filter(|file| !parsed.contains(&normalize(file)))
+        .collect::<Vec<_>>();
+    files.sort();
+    files.dedup();
+    files
+        .par_iter()
+        .filter_map(|path| {
+            let start = Instant::now();
+            let source_text = file_provider.read_to_string(path).ok()?;
+            let imports =
+                parse_rust_source(&source_text, max_parse_size, attribute_crate_refs).ok()?;
+            Some((imports, (path.clone(), start.elapsed())))
+        })
+        .unzip()
+}
+
+/// Removes the names bound by `extern crate foo as bar;` in each crate root from the imports of
+/// the files reachable from it, instead of from the files of all targets as [`merge_imports`]
+/// would. A file reachable from several roots keeps the names which not all of them rename.
+fn apply_extern_crate_renames(
+    roots: &[PathBuf],
+    module_refs: &HashMap<PathBuf, Vec<PathBuf>>,
+    files: Vec<(PathBuf, Imports)>,
+) -> Vec<Imports> {
+    let root_renames = roots
+        .iter()
+        .map(|root| {
+            let root = normalize(root);
+            let renames = files
+                .iter()
+                .find(|(path, _)| *path == root)
+                .map(|(_, imports)| imports.extern_crate_renames.clone())
+                .unwrap_or_default();
+            (root, renames)
+        })
+        .collect::<Vec<_>>();
+    // File -> the names renamed by every root it is reachable from.
+    let mut file_renames = HashMap::<PathBuf, Deps>::new();
+    for (root, renames) in root_renames {
+        let mut reached = HashSet::from([root.clone()]);
+        let mut stack = vec![root];
+        while let Some(file) = stack.pop() {
+            for module in module_refs.get(&file).into_iter().flatten() {
+                if reached.insert(module.clone()) {
+                    stack.push(module.clone());
+                }
+            }
+        }
+        for file in reached {
+            match file_renames.entry(file) {
+                Entry::Vacant(entry) => {
+                    entry.insert(renames.clone());
+                }
+                Entry::Occupied(mut entry) => entry.get_mut().retain(|name| renames.contains(name)),
+            }
+        }
+    }
+    files
+        .into_iter()
+        .map(|(path, mut imports)| {
+            let renames = file_renames.remove(&path).unwrap_or_default();
+            imports.deps.retain(|name| !renames.contains(name));
+            imports.doc_only_deps.retain(|name| !renames.contains(name));
+            imports.extern_crate_renames.clear();
+            imports
+        })
+        .collect()
+}
+
+/// Candidate files of each of the `source_refs` of the file at `path`, and whether their modules
+/// are looked up in their own directory, for each alternative path of a module with
+/// `#[cfg_attr(..., path = "...")]`.
+///
+/// Like `rustc`, modules of crate roots, `mod.rs` and files loaded with `#[path]` are looked up in
+/// their directory (`owns_dir`), and modules of other files in a directory named after them.
+fn referenced_files(
+    path: &Path,
+    owns_dir: bool,
+    source_refs: &[SourceRef],
+) -> Vec<Vec<Vec<(PathBuf, bool)>>> {
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let module_dir = if owns_dir {
+        dir.to_path_buf()
+    } else {
+        path.file_stem().map_or_else(|| dir.to_path_buf(), |stem| dir.join(stem))
+    };
+    let inline_dir = |parents: &[String]| {
+        parents.iter().fold(module_dir.clone(), |dir, parent| dir.join(parent))
+    };
+    // Outside of inline modules, `#[path]` is relative to the directory of the file.
+    let path_dir = |parents: &[String]| {
+        if parents.is_empty() {
+            dir.to_path_buf()
+        } else {
+            inline_dir(parents)
+        }
+    };
+    source_refs
+        .iter()
+        .map(|source_ref| match source_ref {
+            SourceRef::Module { parents, name, path, cfg_paths } => {
+                let default = path.as_ref().map_or_else(
+                    || {
+                        let dir = inline_dir(parents);
+                        vec![
+                            (dir.join(format!("{name}.rs")), false),
+                            (dir.join(name).join("mod.rs"), true),
+                        ]
+                    },
+                    |path| vec![(path_dir(parents).join(path), true)],
+                );
+                let cfg_paths =
+                    cfg_paths.iter().map(|path| vec![(path_dir(parents).join(path), true)]);
+                std::iter::once(default).chain(cfg_paths).collect()
+            }
+            SourceRef::Include(path) => vec![vec![(dir.join(path), owns_dir)]],
+        })
+        .collect()
+}
+
+/// Removes `.` and `a/..` from `path` without touching the file system, so that files reached
+/// through different relative paths are only parsed once.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(normalized.components().next_back(), Some(Component::Normal(_))) =>
+            {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Combines the imports of the files of a package; `extern crate` renames apply to all of them.
+pub fn merge_imports<'a>(imports: impl IntoIterator<Item = &'a Imports> + Clone) -> Imports {
+    let mut package_imports = Imports::default();
+    for imports in imports.clone() {
+        package_imports.extern_crate_renames.extend(imports.extern_crate_renames.iter().cloned());
+        package_imports.derive_crates.extend(imports.derive_crates.iter().cloned());
+        package_imports.exported_macros.extend(
+            imports.exported_macros.iter().map(|(name, crates)| (name.clone(), crates.clone())),
+        );
+        package_imports.macro_calls.extend(imports.macro_calls.iter().cloned());
+        package_imports.item_paths.extend(imports.item_paths.iter().cloned());
+    }
+    package_imports.deps = imports
+        .clone()
+        .into_iter()
+        .flat_map(|imports| imports.deps.iter())
+        .filter(|name| !package_imports.extern_crate_renames.contains(*name))
+        .cloned()
+        .collect();
+    // Used only for documentation builds unless some file uses them otherwise.
+    let used_otherwise = imports
+        .clone()
+        .into_iter()
+        .flat_map(|imports| imports.deps.difference(&imports.doc_only_deps))
+        .collect::<HashSet<_>>();
+    package_imports.doc_only_deps = imports
+        .into_iter()
+        .flat_map(|imports| imports.doc_only_deps.iter())
+        .filter(|name| !used_otherwise.contains(name))
+        .cloned()
+        .collect();
+    package_imports
+}
+
+pub fn process_rust_source(
+    file_provider: &dyn FileProvider,
+    path: &Path,
+    max_parse_size: usize,
+    attribute_crate_refs: &[AttributeCrateRef],
+) -> Result<Imports> {
+    let source_text = file_provider.read_to_string(path)?;
+    parse_rust_source(&source_text, max_parse_size, attribute_crate_refs)
+}
+
+fn parse_rust_source(
+    source_text: &str,
+    max_parse_size: usize,
+    attribute_crate_refs: &[AttributeCrateRef],
+) -> Result<Imports> {
+    if source_text.len() > max_parse_size {
+        return Ok(scan_imports(source_text));
+    }
+    let imports = collect_imports(source_text, attribute_crate_refs)?;
+    Ok(imports)
+}
+
+/// Module names of `dependencies` (module name -> package name) which are not imported, sorted.
+pub fn find_unused_module_names(
+    dependencies: &HashMap<String, String>,
+    mut imports: Deps,
+    aliases: &HashMap<&str, &str>,
+    known_imports: &HashSet<&str>,
+) -> Vec<String> {
+    imports.retain(|name| !known_imports.contains(name.as_str()));
+
+    // e.g. `aliases = { "json" = "serde_json" }` credits `json::Value` to `serde_json`
+    for (alias, dependency) in aliases {
+        if imports.contains(*alias) {
+            let module_name = dependency.replace('-', "_");
+            imports.extend(
+                dependencies
+                    .iter()
+                    .filter(|(module, name)| **module == module_name || name == dependency)
+                    .map(|(module, _)| module.clone()),
+            );
+        }
+    }
+
+    let mut unused = dependencies
+        .keys()
+        .filter(|module| !imports.contains(*module))
+        .cloned()
+        .collect::<Vec<_>>();
+    unused.sort();
+    unused
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{collect_reachable_imports_timed, PackageAnalysisBuilder};
+    use crate::{file_provider::RealFileProvider, testing::Fixture, MemoryFileProvider};
+
+    #[test]
+    fn analyze_in_memory_package() {
+        let mut file_provider = MemoryFileProvider::new();
+        file_provider.insert(
+            "/virtual/Cargo.toml",
+            r#"
+[package]
+name = "virtual"
+version = "0.1.0"
+
+[dependencies]
+serde_json = "1"
+json5 = { package = "json5-alt", version = "1" }
+itoa = "1"
+ryu = "1"
+
+[package.metadata.cargo-shear]
+ignored = ["ryu"]
+"#,
+        );
+        file_provider.insert("/virtual/src/lib.rs", "use serde_json::Value;");
+
+        let analysis = PackageAnalysisBuilder::new("/virtual/Cargo.toml")
+            .files(["/virtual/src/lib.rs"])
+            .file_provider(file_provider)
+            .analyze()
+            .unwrap();
+        assert_eq!(analysis.package_name, "virtual");
+        assert_eq!(analysis.unused_dependencies, vec!["itoa", "json5-alt"]);
+    }
+
+    #[test]
+    fn reachable_files() {
+        let mut file_provider = MemoryFileProvider::new();
+        for (path, source) in [
+            (
+                "/virtual/src/lib.rs",
+                r#"mod a; mod b { mod c; } #[path = "../shared/mod.rs"] mod d; include!("e.rs");"#,
+            ),
+            ("/virtual/src/a.rs", "mod f; use a_dep::A;"),
+            ("/virtual/src/a/f.rs", "use f_dep::F;"),
+            ("/virtual/src/b/c/mod.rs", "use c_dep::C;"),
+            ("/virtual/shared/mod.rs", "mod g; use d_dep::D;"),
+            ("/virtual/shared/g.rs", "use g_dep::G;"),
+            ("/virtual/src/e.rs", "use e_dep::E;"),
+            ("/virtual/src/bin/main.rs", "cfg_if::cfg_if! { if #[cfg(unix)] { mod h; } }"),
+            ("/virtual/src/bin/h.rs", "use h_dep::H;"),
+            ("/virtual/src/dead.rs", "use dead_dep::Dead;"),
+        ] {
+            file_provider.insert(path, source);
+        }
+
+        let roots =
+            [PathBuf::from("/virtual/src/lib.rs"), PathBuf::from("/virtual/src/bin/main.rs")];
+        let (imports, times) =
+            collect_reachable_imports_timed(&file_provider, &roots, usize::MAX, &[]).unwrap();
+        let mut deps = imports.deps.into_iter().collect::<Vec<_>>();
+        deps.sort();
+        assert_eq!(deps, ["a_dep", "c_dep", "cfg_if", "d_dep", "e_dep", "f_dep", "g_dep", "h_dep"]);
+        assert_eq!(times.len(), 9);
+
+        let missing = [PathBuf::from("/virtual/src/missing.rs")];
+        assert!(collect_reachable_imports_timed(&file_provider, &missing, usize::MAX, &[]).is_err());
+    }
+
+    #[test]
+    fn cfg_attr_module_paths() {
+        let mut file_provider = MemoryFileProvider::new();
+        for (path, source) in [
+            (
+                "/virtual/src/lib.rs",
+                r#"#[cfg_attr(unix, path = "imp/unix.rs")] #[cfg_attr(windows, path = "imp/windows.rs")] mod imp;"#,
+            ),
+            ("/virtual/src/imp/unix.rs", "use unix_dep::A;"),
+            ("/virtual/src/imp/windows.rs", "use windows_dep::B;"),
+        ] {
+            file_provider.insert(path, source);
+        }
+
+        let roots = [PathBuf::from("/virtual/src/lib.rs")];
+        let (imports, _) =
+            collect_reachable_imports_timed(&file_provider, &roots, usize::MAX, &[]).unwrap();
+        let mut deps = imports.deps.into_iter().collect::<Vec<_>>();
+        deps.sort();
+        assert_eq!(deps, ["unix_dep", "windows_dep"]);
+    }
+
+    #[test]
+    fn unresolved_module_scans_target_files() {
+        let fixture = Fixture::workspace(&[]).unwrap();
+        fixture.write("src/lib.rs", "mod generated; use lib_dep::A;").unwrap();
+        fixture.write("src/imp/unix.rs", "use unix_dep::B;").unwrap();
+
+        let roots = [fixture.path().join("src/lib.rs")];
+        let (imports, times) =
+            collect_reachable_imports_timed(&RealFileProvider, &roots, usize::MAX, &[]).unwrap();
+        let mut deps = imports.deps.into_iter().collect::<Vec<_>>();
+        deps.sort();
+        assert_eq!(deps, ["lib_dep", "unix_dep"]);
+        assert_eq!(times.len(), 2);
+    }
+
+    #[test]
+    fn extern_crate_renames_per_root() {
+        let mut file_provider = MemoryFileProvider::new();
+        for (path, source) in [
+            (
+                "/virtual/src/lib.rs",
+                "extern crate foo as bar; extern crate quux as corge; mod a; mod shared;",
+            ),
+            ("/virtual/src/a.rs", "fn f() { bar::x(); corge::y(); }"),
+            ("/virtual/src/shared.rs", "fn f() { baz::x(); }"),
+            (
+                "/virtual/src/main.rs",
+                "extern crate qux as baz; mod shared; fn main() { bar::y(); }",
+            ),
+        ] {
+            file_provider.insert(path, source);
+        }
+
+        let roots = [PathBuf::from("/virtual/src/lib.rs"), PathBuf::from("/virtual/src/main.rs")];
+        let (imports, _) =
+            collect_reachable_imports_timed(&file_provider, &roots, usize::MAX, &[]).unwrap();
+        let mut deps = imports.deps.into_iter().collect::<Vec<_>>();
+        deps.sort();
+        // `bar` is only a rename in the library, and `baz` only in the binary.
+        assert_eq!(deps, ["bar", "baz", "foo", "quux", "qux"]);
+    }
+}
@@ -0,0 +1,166 @@
+use anyhow::{bail, Result};
+use cargo_metadata::{semver::Version, Package};
+
+/// A `--package` or `--exclude` argument, one of cargo's package id specifications:
+/// `name`, `name@version` or a source URL such as `path+file:///path/to/foo#foo@1.0.0`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageSpec {
+    spec: String,
+
+    name: Option<String>,
+
+    /// Full or partial, e.g. `1.2` matches `1.2.3`.
+    version: Option<String>,
+
+    /// Source URL without the `path+` or `registry+` kind.
+    url: Option<String>,
+}
+
+impl PackageSpec {
+    /// # Errors
+    ///
+    /// When the version is invalid.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (url, name_and_version) = match spec.split_once("://") {
+            Some(_) => {
+                let (url, fragment) = spec
+                    .split_once('#')
+                    .map_or((spec, None), |(url, fragment)| (url, Some(fragment)));
+                let url = url.split_once('+').map_or(url, |(_, url)| url);
+                (Some(url.trim_end_matches('/').to_string()), fragment)
+            }
+            None => (None, Some(spec)),
+        };
+        let (name, version) =
+            name_and_version.map_or((None, None), |fragment| match fragment.split_once('@') {
+                Some((name, version)) => (Some(name), Some(version)),
+                // A URL fragment is either a name or a version.
+                None if url.is_some() && fragment.starts_with(|c: char| c.is_ascii_digit()) => {
+                    (None, Some(fragment))
+                }
+                None => (Some(fragment), None),
+            });
+        if let Some(version) = version {
+            let partial = version.split('.').count() <= 3
+                && version.split('.').all(|part| part.parse::<u64>().is_ok());
+            if !partial && Version::parse(version).is_err() {
+                bail!("invalid version `{version}` in package spec `{spec}`");
+            }
+        }
+        Ok(Self {
+            spec: spec.to_string(),
+            name: name.map(str::to_string),
+            version: version.map(str::to_string),
+            url,
+        })
+    }
+
+    pub fn matches(&self, package: &Package) -> bool {
+        self.matches_parts(&package.name, &package.version, &package.id.repr)
+    }
+
+    /// For packages only known by name, e.g. with `--best-effort`.
+    pub fn matches_name(&self, name: &str) -> bool {
+        self.url.is_none() && self.name.as_deref() == Some(name)
+    }
+
+    fn matches_parts(&self, name: &str, version: &Version, id: &str) -> bool {
+        self.name.as_deref().is_none_or(|spec_name| spec_name == name)
+            && self.version.as_deref().is_none_or(|spec_version| {
+                Version::parse(spec_version).map_or_else(
+                    |_| {
+                        let parts = [version.major, version.minor, version.patch];
+                        spec_version
+                            .split('.')
+                            .zip(parts)
+                            .all(|(part, actual)| part.parse() == Ok(actual))
+                    },
+                    |spec_version| spec_version == *version,
+                )
+            })
+            && self.url.as_deref().is_none_or(|url| source_url(id) == url)
+    }
+
+    /// Workspace member names close to the name of this spec, for suggestions.
+    pub fn near_misses<'a>(&self, names: impl IntoIterator<Item = &'a str>) -> Vec<&'a str> {
+        let Some(spec_name) = self.name.as_deref() else { return vec![] };
+        let mut names = names
+            .into_iter()
+            .filter(|name| {
+                name.contains(spec_name)
+                    || spec_name.contains(name)
+                    || edit_distance(name, spec_name) <= (spec_name.len() / 3).max(1)
+            })
+            .collect::<Vec<_>>();
+        names.sort_unstable();
+        names.dedup();
+        names
+    }
+}
+
+impl std::fmt::Display for PackageSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.spec)
+    }
+}
+
+/// The source URL of a package id, in the `path+file:///foo#1.0.0` or the older
+/// `foo 1.0.0 (path+file:///foo)` format, without its kind.
+fn source_url(id: &str) -> &str {
+    let url = match id.split_once(" (") {
+        Some((_, source)) => source.trim_end_matches(')'),
+        None => id.split_once('#').map_or(id, |(url, _)| url),
+    };
+    url.split_once('+').map_or(url, |(_, url)| url).trim_end_matches('/')
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use cargo_metadata::semver::Version;
+
+    use super::PackageSpec;
+
+    #[test]
+    fn specs() {
+        let version = Version::new(0, 1, 2);
+        let id = "path+file:///ws/crates/foo#0.1.2";
+        let matches =
+            |spec: &str| PackageSpec::parse(spec).unwrap().matches_parts("foo", &version, id);
+
+        assert!(matches("foo"));
+        assert!(matches("foo@0.1"));
+        assert!(matches("foo@0.1.2"));
+        assert!(!matches("foo@0.2"));
+        assert!(!matches("bar"));
+        assert!(matches("path+file:///ws/crates/foo"));
+        assert!(matches("file:///ws/crates/foo#foo@0.1.2"));
+        assert!(matches("path+file:///ws/crates/foo#0.1"));
+        assert!(!matches("path+file:///ws/crates/bar#foo"));
+        assert!(PackageSpec::parse("foo@x").is_err());
+
+        let old_id = "foo 0.1.2 (path+file:///ws/crates/foo)";
+        assert!(PackageSpec::parse("file:///ws/crates/foo")
+            .unwrap()
+            .matches_parts("foo", &version, old_id));
+    }
+
+    #[test]
+    fn near_misses() {
+        let spec = PackageSpec::parse("cargo-sheer").unwrap();
+        assert_eq!(spec.near_misses(["cargo-shear", "serde", "cargo"]), ["cargo", "cargo-shear"]);
+    }
+}
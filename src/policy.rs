@@ -0,0 +1,52 @@
+use cargo_metadata::semver::{Version, VersionReq};
+
+/// A `banned` entry, e.g. `openssl` or `chrono<0.5`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BannedCrate<'a> {
+    pub entry: &'a str,
+
+    pub name: &'a str,
+
+    /// Only these versions are banned; all of them if `None`.
+    pub req: Option<VersionReq>,
+}
+
+impl<'a> BannedCrate<'a> {
+    /// # Errors
+    ///
+    /// When the version requirement is invalid.
+    pub fn parse(entry: &'a str) -> Result<Self, String> {
+        let Some(index) = entry.find(['<', '>', '=', '~', '^', '*', ' ']) else {
+            return Ok(Self { entry, name: entry, req: None });
+        };
+        let (name, req) = entry.split_at(index);
+        let req = VersionReq::parse(req.trim()).map_err(|err| {
+            format!("invalid version requirement in `banned` entry `{entry}`: {err}")
+        })?;
+        Ok(Self { entry, name: name.trim(), req: Some(req) })
+    }
+
+    pub fn matches(&self, name: &str, version: &Version) -> bool {
+        self.name == name && self.req.as_ref().is_none_or(|req| req.matches(version))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cargo_metadata::semver::Version;
+
+    use super::BannedCrate;
+
+    #[test]
+    fn banned() {
+        let any = BannedCrate::parse("openssl").unwrap();
+        assert!(any.matches("openssl", &Version::new(0, 10, 0)));
+        assert!(!any.matches("openssl-sys", &Version::new(0, 10, 0)));
+
+        let old = BannedCrate::parse("chrono<0.5").unwrap();
+        assert!(old.matches("chrono", &Version::new(0, 4, 38)));
+        assert!(!old.matches("chrono", &Version::new(0, 5, 0)));
+
+        assert!(BannedCrate::parse("chrono<x").is_err());
+    }
+}
@@ -0,0 +1,215 @@
+//! Helpers for testing cargo-shear's behavior on fixture workspaces, e.g. to check that a
+//! configuration ignores the right dependencies:
+//!
+//! ```ignore
+//! let fixture = Fixture::copy("tests/fixtures/workspace")?;
+//! let report = fixture.check(CheckOptions::new())?;
+//! assert_snapshot(&report, "tests/snapshots/workspace.json");
+//! ```
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process::{self, Command},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use anyhow::Result;
+use similar::TextDiff;
+use walkdir::WalkDir;
+
+use crate::{check, CheckOptions, Report};
+
+/// Set to update snapshot files instead of comparing against them.
+pub const UPDATE_SNAPSHOTS_ENV: &str = "CARGO_SHEAR_UPDATE_SNAPSHOTS";
+
+/// A copy of a fixture workspace in a temporary directory, removed when dropped,
+/// so that `--fix` does not modify the fixture.
+pub struct Fixture {
+    dir: PathBuf,
+}
+
+impl Fixture {
+    /// Copies the workspace at `source`, except `target` directories.
+    ///
+    /// # Errors
+    ///
+    /// When `source` cannot be read or the copy cannot be written.
+    pub fn copy(source: impl AsRef<Path>) -> io::Result<Self> {
+        let source = source.as_ref();
+        let fixture = Self::empty()?;
+        for entry in
+            WalkDir::new(source).into_iter().filter_entry(|entry| entry.file_name() != "target")
+        {
+            let entry = entry?;
+            let relative_path = entry.path().strip_prefix(source).unwrap_or_else(|_| entry.path());
+            let destination = fixture.dir.join(relative_path);
+            if entry.file_type().is_dir() {
+                fs::create_dir_all(destination)?;
+            } else {
+                fs::copy(entry.path(), destination)?;
+            }
+        }
+        Ok(fixture)
+    }
+
+    /// A workspace of `members`, each a name and the rest of its manifest after the `[package]`
+    /// fields, with an empty `src/lib.rs`.
+    ///
+    /// # Errors
+    ///
+    /// When the files cannot be written.
+    pub fn workspace(members: &[(&str, &str)]) -> io::Result<Self> {
+        let fixture = Self::empty()?;
+        for (member, manifest) in members {
+            fixture.write(format!("{member}/src/lib.rs"), "")?;
+            fixture.write(
+                format!("{member}/Cargo.toml"),
+                format!("[package]\nname = \"{member}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n{manifest}"),
+            )?;
+        }
+        let members = members.iter().map(|(member, _)| format!("\"{member}\"")).collect::<Vec<_>>();
+        fixture
+            .write("Cargo.toml", format!("[workspace]\nmembers = [{}]\n", members.join(", ")))?;
+        Ok(fixture)
+    }
+
+    fn empty() -> io::Result<Self> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let name =
+            format!("cargo-shear-{}-{}", process::id(), COUNTER.fetch_add(1, Ordering::Relaxed));
+        let fixture = Self { dir: std::env::temp_dir().join(name) };
+        fs::create_dir_all(&fixture.dir)?;
+        Ok(fixture)
+    }
+
+    /// Commits the files of the copy to a new git repository, e.g. for `--compare-ref`.
+    ///
+    /// # Errors
+    ///
+    /// When `git` cannot be run or fails.
+    pub fn git_init(&self) -> io::Result<()> {
+        for args in [&["init", "-q"][..], &["add", "."], &["commit", "-qm", "init"]] {
+            let status = Command::new("git")
+                .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+                .args(args)
+                .current_dir(&self.dir)
+                .status()?;
+            if !status.success() {
+                return Err(io::Error::other(format!("`git {}` failed", args.join(" "))));
+            }
+        }
+        Ok(())
+    }
+
+    /// Root directory of the copy.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Runs [`check`] on the copy.
+    ///
+    /// # Errors
+    ///
+    /// See [`check`].
+    pub fn check(&self, options: CheckOptions) -> Result<Report> {
+        check(&self.dir, options)
+    }
+
+    /// Reads a file of the copy, e.g. a manifest after `--fix`.
+    ///
+    /// # Errors
+    ///
+    /// When the file cannot be read.
+    pub fn read(&self, relative_path: impl AsRef<Path>) -> io::Result<String> {
+        fs::read_to_string(self.dir.join(relative_path))
+    }
+
+    /// Writes a file of the copy, creating its directory.
+    ///
+    /// # Errors
+    ///
+    /// When the file cannot be written.
+    pub fn write(
+        &self,
+        relative_path: impl AsRef<Path>,
+        contents: impl AsRef<[u8]>,
+    ) -> io::Result<()> {
+        let path = self.dir.join(relative_path);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, contents)
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Asserts that `report`, as pretty printed JSON, matches the contents of `snapshot`.
+///
+/// The snapshot is written instead if it does not exist or [`UPDATE_SNAPSHOTS_ENV`] is set.
+///
+/// # Panics
+///
+/// When the report differs from the snapshot, or the snapshot cannot be written.
+pub fn assert_snapshot(report: &Report, snapshot: impl AsRef<Path>) {
+    let snapshot = snapshot.as_ref();
+    let actual =
+        format!("{}\n", serde_json::to_string_pretty(report).expect("serializable report"));
+    let expected = fs::read_to_string(snapshot).ok();
+    let Some(expected) = expected.filter(|_| std::env::var_os(UPDATE_SNAPSHOTS_ENV).is_none())
+    else {
+        if let Some(parent) = snapshot.parent() {
+            fs::create_dir_all(parent).expect("snapshot directory");
+        }
+        fs::write(snapshot, actual).expect("snapshot file");
+        return;
+    };
+    if expected != actual {
+        let diff = TextDiff::from_lines(&expected, &actual)
+            .unified_diff()
+            .header("snapshot", "report")
+            .to_string();
+        panic!(
+            "report differs from {}, set {UPDATE_SNAPSHOTS_ENV}=1 to update it:\n{diff}",
+            snapshot.display()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{assert_snapshot, Fixture};
+    use crate::CheckOptions;
+
+    #[test]
+    fn fixture() {
+        let source = Fixture::workspace(&[
+            ("app", "\n[dependencies]\nhelper = { path = \"../helper\" }\n"),
+            ("helper", ""),
+        ])
+        .unwrap();
+        let source = source.path();
+
+        let fixture = Fixture::copy(source).unwrap();
+        let report = fixture.check(CheckOptions::new().fix(true)).unwrap();
+        assert_eq!(report.packages[0].unused_dependencies[0].dependency, "helper");
+        assert!(!fixture.read("app/Cargo.toml").unwrap().contains("helper"));
+        assert!(fs::read_to_string(source.join("app/Cargo.toml")).unwrap().contains("helper"));
+
+        let snapshot = source.join("snapshots/app.json");
+        assert_snapshot(&report, &snapshot);
+        assert_snapshot(&report, &snapshot);
+
+        let path = fixture.path().to_path_buf();
+        drop(fixture);
+        assert!(!path.exists());
+    }
+}